@@ -0,0 +1,466 @@
+use crate::config::Config;
+use crate::kconfig;
+
+use std::fmt;
+
+use Expected::{AtLeast, Disabled, Enabled};
+
+/// The value a hardening recommendation asks for.
+#[derive(Clone, Copy, Debug)]
+pub enum Expected {
+    /// Built in (`y`).
+    Enabled,
+    /// Not set or `n`.
+    Disabled,
+    /// A number at least this large.
+    AtLeast(u64),
+}
+
+/// A recommended kernel option in the style of the Kernel Self Protection Project.
+#[derive(Debug)]
+pub struct Recommendation {
+    /// Names of the option, current name first, followed by older names.
+    pub symbols: &'static [&'static str],
+    pub expected: Expected,
+    /// Kernel source architecture (`SRCARCH`) the recommendation applies to,
+    /// `None` for all.
+    pub arch: Option<&'static str>,
+    pub reason: &'static str,
+}
+
+const fn rec(
+    symbols: &'static [&'static str],
+    expected: Expected,
+    arch: Option<&'static str>,
+    reason: &'static str,
+) -> Recommendation {
+    Recommendation {
+        symbols,
+        expected,
+        arch,
+        reason,
+    }
+}
+
+/// The hardening settings every build is audited against.
+pub const RECOMMENDATIONS: &[Recommendation] = &[
+    rec(
+        &["STACKPROTECTOR_STRONG", "CC_STACKPROTECTOR_STRONG"],
+        Enabled,
+        None,
+        "detects stack buffer overflows overwriting return addresses",
+    ),
+    rec(
+        &["FORTIFY_SOURCE"],
+        Enabled,
+        None,
+        "checks buffer sizes of string and memory functions at compile and run time",
+    ),
+    rec(
+        &["INIT_ON_ALLOC_DEFAULT_ON"],
+        Enabled,
+        None,
+        "zeroes heap and page allocations so stale data doesn't leak",
+    ),
+    rec(
+        &["INIT_STACK_ALL_ZERO"],
+        Enabled,
+        None,
+        "zeroes stack variables so uninitialized reads don't leak data",
+    ),
+    rec(
+        &["STRICT_KERNEL_RWX", "DEBUG_RODATA"],
+        Enabled,
+        None,
+        "maps kernel code read-only and data non-executable",
+    ),
+    rec(
+        &["STRICT_MODULE_RWX", "DEBUG_SET_MODULE_RONX"],
+        Enabled,
+        None,
+        "maps module code read-only and data non-executable",
+    ),
+    rec(
+        &["MODULE_SIG_FORCE"],
+        Enabled,
+        None,
+        "refuses to load modules that aren't signed by the build",
+    ),
+    rec(
+        &["SECURITY_LOCKDOWN_LSM"],
+        Enabled,
+        None,
+        "restricts interfaces that let root modify the running kernel",
+    ),
+    rec(
+        &["SECURITY_LOCKDOWN_LSM_EARLY"],
+        Enabled,
+        None,
+        "enforces lockdown from early boot on",
+    ),
+    rec(
+        &["LOCK_DOWN_KERNEL_FORCE_CONFIDENTIALITY"],
+        Enabled,
+        None,
+        "enables lockdown in its strictest mode regardless of the command line",
+    ),
+    rec(
+        &["RANDOMIZE_BASE"],
+        Enabled,
+        None,
+        "randomizes the kernel address (KASLR)",
+    ),
+    rec(
+        &["VMAP_STACK"],
+        Enabled,
+        None,
+        "puts kernel stacks in vmalloc space with guard pages",
+    ),
+    rec(
+        &["HARDENED_USERCOPY"],
+        Enabled,
+        None,
+        "bounds checks copies between kernel and user space",
+    ),
+    rec(
+        &["SLAB_FREELIST_RANDOM"],
+        Enabled,
+        None,
+        "randomizes slab allocation order",
+    ),
+    rec(
+        &["SLAB_FREELIST_HARDENED"],
+        Enabled,
+        None,
+        "protects slab freelist metadata against corruption",
+    ),
+    rec(
+        &["SHUFFLE_PAGE_ALLOCATOR"],
+        Enabled,
+        None,
+        "randomizes page allocation order",
+    ),
+    rec(
+        &["LIST_HARDENED", "DEBUG_LIST"],
+        Enabled,
+        None,
+        "checks linked list integrity on every modification",
+    ),
+    rec(
+        &["BUG_ON_DATA_CORRUPTION"],
+        Enabled,
+        None,
+        "stops instead of continuing after detecting corrupted data structures",
+    ),
+    rec(
+        &["SCHED_STACK_END_CHECK"],
+        Enabled,
+        None,
+        "detects kernel stack overflows on context switch",
+    ),
+    rec(
+        &["SECCOMP_FILTER"],
+        Enabled,
+        None,
+        "lets services restrict the system calls they can make",
+    ),
+    rec(
+        &["SECURITY_DMESG_RESTRICT"],
+        Enabled,
+        None,
+        "hides kernel log messages, which may contain addresses, from unprivileged users",
+    ),
+    rec(
+        &["BPF_UNPRIV_DEFAULT_OFF"],
+        Enabled,
+        None,
+        "disallows unprivileged BPF programs by default",
+    ),
+    rec(
+        &["STRICT_DEVMEM"],
+        Enabled,
+        None,
+        "restricts /dev/mem to device memory",
+    ),
+    rec(
+        &["IO_STRICT_DEVMEM"],
+        Enabled,
+        None,
+        "denies /dev/mem access to memory claimed by drivers",
+    ),
+    rec(
+        &["COMPAT_BRK"],
+        Disabled,
+        None,
+        "disables heap randomization for old programs",
+    ),
+    rec(
+        &["PROC_KCORE"],
+        Disabled,
+        None,
+        "exposes kernel memory through /proc/kcore",
+    ),
+    rec(
+        &["LEGACY_PTYS"],
+        Disabled,
+        None,
+        "enables unused BSD-style pseudo terminals",
+    ),
+    rec(
+        &["HIBERNATION"],
+        Disabled,
+        None,
+        "allows replacing the running kernel image from disk",
+    ),
+    rec(
+        &["KEXEC"],
+        Disabled,
+        None,
+        "allows replacing the running kernel at run time",
+    ),
+    rec(
+        &["ACPI_CUSTOM_METHOD"],
+        Disabled,
+        None,
+        "allows writing kernel memory through ACPI methods",
+    ),
+    rec(
+        &["DEBUG_FS"],
+        Disabled,
+        None,
+        "exposes a large, unhardened interface to kernel internals",
+    ),
+    rec(
+        &["DEFAULT_MMAP_MIN_ADDR"],
+        AtLeast(65536),
+        Some("x86"),
+        "keeps user space from mapping low addresses to exploit NULL dereferences",
+    ),
+    rec(
+        &["MITIGATION_PAGE_TABLE_ISOLATION", "PAGE_TABLE_ISOLATION"],
+        Enabled,
+        Some("x86"),
+        "unmaps the kernel while running user space (Meltdown)",
+    ),
+    rec(
+        &["MITIGATION_RETPOLINE", "RETPOLINE"],
+        Enabled,
+        Some("x86"),
+        "avoids speculative indirect branches (Spectre v2)",
+    ),
+    rec(
+        &["LEGACY_VSYSCALL_NONE"],
+        Enabled,
+        Some("x86"),
+        "removes the fixed-address vsyscall page",
+    ),
+    rec(
+        &["X86_UMIP"],
+        Enabled,
+        Some("x86"),
+        "keeps user space from reading descriptor table addresses",
+    ),
+    rec(
+        &["DEFAULT_MMAP_MIN_ADDR"],
+        AtLeast(32768),
+        Some("arm64"),
+        "keeps user space from mapping low addresses to exploit NULL dereferences",
+    ),
+    rec(
+        &["UNMAP_KERNEL_AT_EL0"],
+        Enabled,
+        Some("arm64"),
+        "unmaps the kernel while running user space (Meltdown)",
+    ),
+    rec(
+        &["ARM64_SW_TTBR0_PAN"],
+        Enabled,
+        Some("arm64"),
+        "blocks kernel access to user memory on CPUs without hardware PAN",
+    ),
+    rec(
+        &["ARM64_PTR_AUTH_KERNEL", "ARM64_PTR_AUTH"],
+        Enabled,
+        Some("arm64"),
+        "signs return addresses on CPUs with pointer authentication",
+    ),
+    rec(
+        &["ARM64_BTI_KERNEL"],
+        Enabled,
+        Some("arm64"),
+        "restricts indirect branch targets on CPUs with BTI",
+    ),
+];
+
+/// The outcome of checking one recommendation.
+#[derive(Debug)]
+pub struct Finding {
+    pub recommendation: &'static Recommendation,
+    /// The name the option has in the audited config.
+    pub symbol: &'static str,
+    /// The value in the audited config, `None` if not set.
+    pub actual: Option<String>,
+    pub passed: bool,
+}
+
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let status = if self.passed { "PASS" } else { "FAIL" };
+
+        match &self.actual {
+            Some(value) => write!(f, "{} CONFIG_{}={}", status, self.symbol, value)?,
+            None => write!(f, "{} CONFIG_{} is not set", status, self.symbol)?,
+        }
+
+        if !self.passed {
+            match self.recommendation.expected {
+                Enabled => write!(f, " (want y)")?,
+                Disabled => write!(f, " (want n)")?,
+                AtLeast(min) => write!(f, " (want at least {})", min)?,
+            }
+        }
+
+        write!(f, ": {}", self.recommendation.reason)
+    }
+}
+
+/// Checks a final `.config` against the recommendations for `arch`,
+/// which may be given as `ARCH` or `SRCARCH`, e.g. `x86_64` or `x86`.
+pub fn audit(config: &Config, arch: &str) -> Vec<Finding> {
+    let arch = kconfig::srcarch(arch);
+
+    RECOMMENDATIONS
+        .iter()
+        .filter(|recommendation| recommendation.arch.is_none_or(|only| only == arch))
+        .map(|recommendation| {
+            let symbol = recommendation
+                .symbols
+                .iter()
+                .find(|symbol| config.get(symbol).is_some())
+                .unwrap_or(&recommendation.symbols[0]);
+
+            let actual = config
+                .get(symbol)
+                .filter(|value| *value != "n")
+                .map(str::to_string);
+
+            let passed = match (recommendation.expected, actual.as_deref()) {
+                (Enabled, Some(value)) => value == "y",
+                (Enabled, None) => false,
+                (Disabled, value) => value.is_none(),
+                (AtLeast(min), Some(value)) => parse_number(value).is_some_and(|n| n >= min),
+                (AtLeast(_), None) => false,
+            };
+
+            Finding {
+                recommendation,
+                symbol,
+                actual,
+                passed,
+            }
+        })
+        .collect()
+}
+
+fn parse_number(value: &str) -> Option<u64> {
+    match value.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16).ok(),
+        None => value.parse().ok(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::path::Path;
+
+    const CONFIG: &str = "\
+CONFIG_STACKPROTECTOR_STRONG=y
+CONFIG_FORTIFY_SOURCE=n
+# CONFIG_DEBUG_FS is not set
+CONFIG_PROC_KCORE=y
+CONFIG_DEBUG_RODATA=y
+CONFIG_DEFAULT_MMAP_MIN_ADDR=0x8000
+CONFIG_RETPOLINE=y
+CONFIG_UNMAP_KERNEL_AT_EL0=y
+";
+
+    fn finding<'a>(findings: &'a [Finding], symbol: &str) -> Option<&'a Finding> {
+        findings
+            .iter()
+            .find(|finding| finding.recommendation.symbols[0] == symbol)
+    }
+
+    #[test]
+    fn recommendations() {
+        let mut config = Config::default();
+        config.merge_text(Path::new("test.config"), CONFIG).unwrap();
+
+        // (arch, first symbol, symbol found, passed)
+        let cases = [
+            (
+                "x86_64",
+                "STACKPROTECTOR_STRONG",
+                "STACKPROTECTOR_STRONG",
+                true,
+            ),
+            ("x86_64", "FORTIFY_SOURCE", "FORTIFY_SOURCE", false),
+            ("x86_64", "DEBUG_FS", "DEBUG_FS", true),
+            ("x86_64", "PROC_KCORE", "PROC_KCORE", false),
+            ("x86_64", "STRICT_KERNEL_RWX", "DEBUG_RODATA", true),
+            (
+                "x86_64",
+                "DEFAULT_MMAP_MIN_ADDR",
+                "DEFAULT_MMAP_MIN_ADDR",
+                false,
+            ),
+            ("x86_64", "MITIGATION_RETPOLINE", "RETPOLINE", true),
+            ("x86", "MITIGATION_RETPOLINE", "RETPOLINE", true),
+            ("x86", "X86_UMIP", "X86_UMIP", false),
+            (
+                "arm64",
+                "DEFAULT_MMAP_MIN_ADDR",
+                "DEFAULT_MMAP_MIN_ADDR",
+                true,
+            ),
+            ("arm64", "UNMAP_KERNEL_AT_EL0", "UNMAP_KERNEL_AT_EL0", true),
+        ];
+
+        for (arch, symbol, found, passed) in cases {
+            let findings = audit(&config, arch);
+            let finding = finding(&findings, symbol)
+                .unwrap_or_else(|| panic!("no finding for {} on {}", symbol, arch));
+
+            assert_eq!(finding.symbol, found, "{} on {}", symbol, arch);
+            assert_eq!(finding.passed, passed, "{} on {}", symbol, arch);
+        }
+    }
+
+    #[test]
+    fn arch_specific() {
+        let config = Config::default();
+
+        for (arch, present, absent) in [
+            ("x86_64", "X86_UMIP", "UNMAP_KERNEL_AT_EL0"),
+            ("x86", "X86_UMIP", "UNMAP_KERNEL_AT_EL0"),
+            ("arm64", "UNMAP_KERNEL_AT_EL0", "X86_UMIP"),
+            ("riscv", "STACKPROTECTOR_STRONG", "X86_UMIP"),
+        ] {
+            let findings = audit(&config, arch);
+
+            assert!(
+                finding(&findings, present).is_some(),
+                "{} on {}",
+                present,
+                arch
+            );
+            assert!(
+                finding(&findings, absent).is_none(),
+                "{} on {}",
+                absent,
+                arch
+            );
+        }
+    }
+}
@@ -0,0 +1,133 @@
+use crate::mirror::{self, Mirrors};
+use crate::verify;
+use crate::version::Version;
+
+use anyhow::{bail, Context};
+use reqwest::header::RANGE;
+use reqwest::StatusCode;
+use std::env;
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Persistent storage for downloaded kernel sources,
+/// keyed by version and checksum.
+#[derive(Debug)]
+pub struct Cache {
+    dir: PathBuf,
+}
+
+impl Cache {
+    /// Opens the cache at `dir`, defaulting to `$XDG_CACHE_HOME/rustkrazy-kernel`
+    /// or `~/.cache/rustkrazy-kernel`.
+    pub fn new(dir: Option<PathBuf>) -> anyhow::Result<Self> {
+        let dir = match dir {
+            Some(dir) => dir,
+            None => match env::var_os("XDG_CACHE_HOME").filter(|v| !v.is_empty()) {
+                Some(cache_home) => PathBuf::from(cache_home).join("rustkrazy-kernel"),
+                None => match env::var_os("HOME") {
+                    Some(home) => PathBuf::from(home).join(".cache/rustkrazy-kernel"),
+                    None => bail!("neither XDG_CACHE_HOME nor HOME is set, use --cache-dir"),
+                },
+            },
+        };
+
+        fs::create_dir_all(&dir)
+            .with_context(|| format!("failed to create cache dir {}", dir.display()))?;
+
+        Ok(Self {
+            dir: dir.canonicalize()?,
+        })
+    }
+
+    /// Returns the cache directory of a kernel version, creating it if needed.
+    pub fn version_dir(&self, version: &Version) -> anyhow::Result<PathBuf> {
+        let dir = self.dir.join(version.name());
+        fs::create_dir_all(&dir)?;
+
+        Ok(dir)
+    }
+
+    /// Returns the path of a file of `version` with the given checksum,
+    /// e.g. `<sha256>.tar.xz` for the tarball or `<sha256>.patch.xz` for the stable patch.
+    pub fn file(&self, version: &Version, sha256: &str, ext: &str) -> anyhow::Result<PathBuf> {
+        Ok(self
+            .version_dir(version)?
+            .join(format!("{}.{}", sha256.to_lowercase(), ext)))
+    }
+
+    /// Makes sure `path` holds the file at `src` on the mirrors with the given checksum,
+    /// reusing a cached copy and resuming interrupted downloads.
+    pub fn get(
+        &self,
+        mirrors: &Mirrors,
+        src: &str,
+        path: &Path,
+        sha256: &str,
+    ) -> anyhow::Result<()> {
+        if path.exists() {
+            if verify::sha256(path)?.eq_ignore_ascii_case(sha256) {
+                println!("Using cached {}", path.display());
+                return Ok(());
+            }
+
+            println!("Cached {} is corrupt, downloading again", path.display());
+            fs::remove_file(path)?;
+        }
+
+        let mut part = path.as_os_str().to_owned();
+        part.push(".part");
+        let part = PathBuf::from(part);
+
+        mirrors.try_each(src, |url| {
+            download_resumable(url, &part)?;
+
+            let actual = verify::sha256(&part)?;
+            if !actual.eq_ignore_ascii_case(sha256) {
+                fs::remove_file(&part)?;
+                bail!(
+                    "checksum mismatch for {}: expected {}, got {}",
+                    url,
+                    sha256,
+                    actual
+                );
+            }
+
+            Ok(())
+        })?;
+
+        fs::rename(part, path)?;
+        Ok(())
+    }
+}
+
+/// Downloads `url` to `path`, continuing from the end of an existing
+/// partial file using an HTTP Range request if the server supports it.
+fn download_resumable(url: &str, path: &Path) -> anyhow::Result<()> {
+    if !mirror::is_remote(url) {
+        return mirror::download(url, path);
+    }
+
+    let offset = fs::metadata(path).map(|meta| meta.len()).unwrap_or(0);
+
+    let mut request = mirror::client()?.get(url);
+    if offset > 0 {
+        println!("Resuming download at byte {}", offset);
+        request = request.header(RANGE, format!("bytes={}-", offset));
+    }
+
+    let mut resp = request.send()?;
+
+    let mut file = match resp.status() {
+        StatusCode::PARTIAL_CONTENT => File::options().append(true).open(path)?,
+        // The partial file already holds the whole body.
+        StatusCode::RANGE_NOT_SATISFIABLE if offset > 0 => return Ok(()),
+        _ => {
+            resp = resp.error_for_status()?;
+            File::create(path)?
+        }
+    };
+
+    io::copy(&mut resp, &mut file)?;
+    Ok(())
+}
@@ -0,0 +1,368 @@
+use crate::fragment::{self, Diagnostic, Severity};
+use crate::target::Target;
+
+use anyhow::{bail, Context};
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Kernel options merged from config fragments,
+/// later assignments overriding earlier ones.
+#[derive(Debug, Default)]
+pub struct Config {
+    options: Vec<(String, String)>,
+    index: HashMap<String, usize>,
+}
+
+impl Config {
+    /// Sets `symbol` (without the `CONFIG_` prefix) to `value`,
+    /// which is `n` for options that are not set.
+    pub fn set(&mut self, symbol: &str, value: &str) {
+        match self.index.get(symbol) {
+            Some(&i) => self.options[i].1 = value.to_string(),
+            None => {
+                self.index.insert(symbol.to_string(), self.options.len());
+                self.options.push((symbol.to_string(), value.to_string()));
+            }
+        }
+    }
+
+    /// Returns the value of `symbol`, if it is assigned.
+    pub fn get(&self, symbol: &str) -> Option<&str> {
+        self.index.get(symbol).map(|&i| self.options[i].1.as_str())
+    }
+
+    /// Returns the assignments in the order they were first made.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.options
+            .iter()
+            .map(|(symbol, value)| (symbol.as_str(), value.as_str()))
+    }
+
+    /// Reads a `.config` file or fragment.
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let mut config = Self::default();
+        config.merge_file(path)?;
+
+        Ok(config)
+    }
+
+    /// Merges the options of a fragment file into the config.
+    pub fn merge_file(&mut self, path: &Path) -> anyhow::Result<()> {
+        let fragment = fs::read_to_string(path)
+            .with_context(|| format!("failed to read config fragment {}", path.display()))?;
+
+        self.merge_text(path, &fragment)
+    }
+
+    /// Merges the options of fragment text read from `path` into the config.
+    pub fn merge_text(&mut self, path: &Path, fragment: &str) -> anyhow::Result<()> {
+        let (assignments, diagnostics) = fragment::parse(path, fragment);
+
+        let errors = diagnostics
+            .iter()
+            .filter(|diagnostic| diagnostic.severity == Severity::Error)
+            .map(|diagnostic| diagnostic.to_string())
+            .collect::<Vec<_>>();
+
+        if !errors.is_empty() {
+            bail!("invalid config fragment:\n{}", errors.join("\n"));
+        }
+
+        for assignment in assignments {
+            self.set(&assignment.symbol, &assignment.value);
+        }
+
+        Ok(())
+    }
+
+    /// Renders the config in `.config` fragment syntax.
+    pub fn to_fragment(&self) -> String {
+        let mut fragment = String::new();
+
+        for (symbol, value) in &self.options {
+            if value == "n" {
+                fragment += &format!("# CONFIG_{} is not set\n", symbol);
+            } else {
+                fragment += &format!("CONFIG_{}={}\n", symbol, value);
+            }
+        }
+
+        fragment
+    }
+}
+
+/// A named set of optional features backed by `profile/<name>.config`.
+#[derive(Clone, Debug)]
+pub struct Profile {
+    pub name: String,
+    pub path: PathBuf,
+    /// The first comment line of the fragment.
+    pub description: String,
+    /// Additional fragments for single architectures, `profile/<name>/<arch>.config`.
+    pub arch_paths: Vec<(String, PathBuf)>,
+}
+
+impl Profile {
+    fn new(path: PathBuf) -> anyhow::Result<Self> {
+        let name = path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .context("invalid profile file name")?
+            .to_string();
+
+        let text = fs::read_to_string(&path)
+            .with_context(|| format!("failed to read profile {}", path.display()))?;
+
+        let description = text
+            .lines()
+            .find_map(|line| line.strip_prefix("# "))
+            .unwrap_or_default()
+            .to_string();
+
+        let mut arch_paths = Vec::new();
+        let arch_dir = path.with_extension("");
+
+        if arch_dir.is_dir() {
+            for entry in fs::read_dir(&arch_dir)? {
+                let arch_path = entry?.path();
+
+                if let Some(arch) = arch_path
+                    .file_stem()
+                    .and_then(|stem| stem.to_str())
+                    .filter(|_| arch_path.extension().is_some_and(|ext| ext == "config"))
+                {
+                    arch_paths.push((arch.to_string(), arch_path.clone()));
+                }
+            }
+        }
+
+        arch_paths.sort();
+
+        Ok(Self {
+            name,
+            path,
+            description,
+            arch_paths,
+        })
+    }
+}
+
+/// Returns the profiles in `profile/` below `dir` in name order.
+pub fn profiles(dir: &Path) -> anyhow::Result<Vec<Profile>> {
+    let dir = dir.join("profile");
+    let mut profiles = Vec::new();
+
+    for entry in fs::read_dir(&dir).with_context(|| format!("failed to read {}", dir.display()))? {
+        let path = entry?.path();
+
+        if path.extension().is_some_and(|ext| ext == "config") {
+            profiles.push(Profile::new(path)?);
+        }
+    }
+
+    profiles.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(profiles)
+}
+
+/// Looks up profiles by name.
+pub fn select_profiles(dir: &Path, names: &[String]) -> anyhow::Result<Vec<Profile>> {
+    let available = profiles(dir)?;
+
+    names
+        .iter()
+        .map(
+            |name| match available.iter().find(|profile| &profile.name == name) {
+                Some(profile) => Ok(profile.clone()),
+                None => bail!(
+                    "unknown profile {} (available: {})",
+                    name,
+                    available
+                        .iter()
+                        .map(|profile| profile.name.as_str())
+                        .collect::<Vec<_>>()
+                        .join(" ")
+                ),
+            },
+        )
+        .collect()
+}
+
+/// Returns the fragment files that make up the config of a target in merge order:
+/// `common.config` from `dir`, the target's own fragments (usually including
+/// `arch/<arch>.config`), the selected profiles, each followed by its fragment
+/// for the architecture if it has one, and finally `extra`.
+pub fn fragments(
+    dir: &Path,
+    target: &Target,
+    profiles: &[Profile],
+    extra: &[PathBuf],
+) -> Vec<PathBuf> {
+    let mut fragments = vec![dir.join("common.config")];
+
+    fragments.extend(target.fragments.iter().map(|fragment| dir.join(fragment)));
+
+    for profile in profiles {
+        fragments.push(profile.path.clone());
+        fragments.extend(
+            profile
+                .arch_paths
+                .iter()
+                .filter(|(arch, _)| *arch == target.arch)
+                .map(|(_, path)| path.clone()),
+        );
+    }
+    fragments.extend(extra.iter().cloned());
+    fragments
+}
+
+/// Reports options that a fragment of `target` sets to the value an earlier
+/// fragment already gave them. Repeats between two profiles are allowed
+/// because each profile has to work on its own.
+pub fn redundant(
+    dir: &Path,
+    target: &Target,
+    profiles: &[Profile],
+) -> anyhow::Result<Vec<Diagnostic>> {
+    let standalone = profiles
+        .iter()
+        .flat_map(|profile| {
+            [&profile.path]
+                .into_iter()
+                .chain(profile.arch_paths.iter().map(|(_, path)| path))
+        })
+        .collect::<Vec<_>>();
+
+    let mut seen: HashMap<String, (PathBuf, usize, String)> = HashMap::new();
+    let mut diagnostics = Vec::new();
+
+    for path in fragments(dir, target, profiles, &[]) {
+        let text = fs::read_to_string(&path)
+            .with_context(|| format!("failed to read config fragment {}", path.display()))?;
+
+        for assignment in fragment::parse(&path, &text).0 {
+            if let Some((previous, line, value)) = seen.get(&assignment.symbol) {
+                let both_profiles = standalone.contains(&previous) && standalone.contains(&&path);
+
+                if *value == assignment.value && !both_profiles && *previous != path {
+                    diagnostics.push(Diagnostic {
+                        path: path.clone(),
+                        line: assignment.line,
+                        severity: Severity::Warning,
+                        message: format!(
+                            "CONFIG_{}={} is already set by {}:{} for target {}",
+                            assignment.symbol,
+                            assignment.value,
+                            previous.display(),
+                            line,
+                            target.name
+                        ),
+                    });
+                }
+            }
+
+            seen.insert(
+                assignment.symbol,
+                (path.clone(), assignment.line, assignment.value),
+            );
+        }
+    }
+
+    Ok(diagnostics)
+}
+
+/// Returns all `*.config` fragment files below `dir` in name order.
+pub fn find(dir: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    let mut fragments = Vec::new();
+
+    for entry in fs::read_dir(dir).with_context(|| format!("failed to read {}", dir.display()))? {
+        let path = entry?.path();
+
+        if path.is_dir() {
+            fragments.extend(find(&path)?);
+        } else if path.extension().is_some_and(|ext| ext == "config") {
+            fragments.push(path);
+        }
+    }
+
+    fragments.sort();
+    Ok(fragments)
+}
+
+/// Merges fragment files in order.
+pub fn merge(fragments: &[PathBuf]) -> anyhow::Result<Config> {
+    let mut config = Config::default();
+
+    for fragment in fragments {
+        config.merge_file(fragment)?;
+    }
+
+    Ok(config)
+}
+
+/// A requested option that `make olddefconfig` did not keep as requested.
+#[derive(Debug)]
+pub enum Mismatch {
+    /// The option is not set at all, usually because of unmet dependencies
+    /// or because the symbol no longer exists.
+    Dropped { symbol: String, requested: String },
+    /// The option was requested built-in but is only built as a module.
+    Downgraded { symbol: String },
+    /// The option ended up with a different value.
+    Changed {
+        symbol: String,
+        requested: String,
+        actual: String,
+    },
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Dropped { symbol, requested } => {
+                write!(f, "CONFIG_{} dropped (requested {})", symbol, requested)
+            }
+            Self::Downgraded { symbol } => {
+                write!(f, "CONFIG_{} downgraded from y to m", symbol)
+            }
+            Self::Changed {
+                symbol,
+                requested,
+                actual,
+            } => write!(
+                f,
+                "CONFIG_{} changed (requested {}, got {})",
+                symbol, requested, actual
+            ),
+        }
+    }
+}
+
+/// Compares the requested options against the final `.config`.
+pub fn check(requested: &Config, actual: &Config) -> Vec<Mismatch> {
+    let mut mismatches = Vec::new();
+
+    for (symbol, requested) in requested.iter() {
+        let actual = actual.get(symbol).unwrap_or("n");
+        if actual == requested {
+            continue;
+        }
+
+        let symbol = symbol.to_string();
+        mismatches.push(match (requested, actual) {
+            ("y", "m") => Mismatch::Downgraded { symbol },
+            (_, "n") => Mismatch::Dropped {
+                symbol,
+                requested: requested.to_string(),
+            },
+            _ => Mismatch::Changed {
+                symbol,
+                requested: requested.to_string(),
+                actual: actual.to_string(),
+            },
+        });
+    }
+
+    mismatches
+}
@@ -0,0 +1,275 @@
+use crate::config::Config;
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::path::Path;
+
+/// How an option differs between two configs.
+#[derive(Debug)]
+pub enum Change {
+    /// The symbol doesn't appear in the old config at all.
+    Added { value: String },
+    /// The symbol doesn't appear in the new config at all.
+    Removed { value: String },
+    /// The symbol appears in both with different values.
+    Changed { old: String, new: String },
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Added { value } => write!(f, "+ {}", value),
+            Self::Removed { value } => write!(f, "- {}", value),
+            Self::Changed { old, new } => write!(f, "~ {} -> {}", old, new),
+        }
+    }
+}
+
+/// The differences between two configs grouped by menu, e.g. `Networking support > Networking options`.
+#[derive(Debug, Default)]
+pub struct Diff {
+    pub menus: BTreeMap<String, Vec<(String, Change)>>,
+}
+
+impl Diff {
+    /// Compares two `.config` files given as text. Symbols are placed
+    /// in the menu they appear in in the new config, or in the old one if removed.
+    pub fn new(old_path: &Path, old: &str, new_path: &Path, new: &str) -> anyhow::Result<Self> {
+        let mut old_config = Config::default();
+        old_config.merge_text(old_path, old)?;
+        let mut new_config = Config::default();
+        new_config.merge_text(new_path, new)?;
+
+        let old_menus = menus(old);
+        let new_menus = menus(new);
+
+        let mut diff = Self::default();
+
+        for (symbol, value) in new_config.iter() {
+            let change = match old_config.get(symbol) {
+                None => Change::Added {
+                    value: value.to_string(),
+                },
+                Some(old) if old != value => Change::Changed {
+                    old: old.to_string(),
+                    new: value.to_string(),
+                },
+                Some(_) => continue,
+            };
+
+            diff.add(&new_menus, symbol, change);
+        }
+
+        for (symbol, value) in old_config.iter() {
+            if new_config.get(symbol).is_none() {
+                let change = Change::Removed {
+                    value: value.to_string(),
+                };
+
+                diff.add(&old_menus, symbol, change);
+            }
+        }
+
+        Ok(diff)
+    }
+
+    fn add(&mut self, menus: &HashMap<String, String>, symbol: &str, change: Change) {
+        let menu = menus.get(symbol).cloned().unwrap_or_default();
+
+        self.menus
+            .entry(menu)
+            .or_default()
+            .push((symbol.to_string(), change));
+    }
+
+    /// Counts the added, removed and changed symbols.
+    pub fn counts(&self) -> (usize, usize, usize) {
+        let mut counts = (0, 0, 0);
+
+        for (_, change) in self.menus.values().flatten() {
+            match change {
+                Change::Added { .. } => counts.0 += 1,
+                Change::Removed { .. } => counts.1 += 1,
+                Change::Changed { .. } => counts.2 += 1,
+            }
+        }
+
+        counts
+    }
+}
+
+impl fmt::Display for Diff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (menu, changes) in &self.menus {
+            if menu.is_empty() {
+                writeln!(f, "(top level)")?;
+            } else {
+                writeln!(f, "{}", menu)?;
+            }
+
+            for (symbol, change) in changes {
+                writeln!(f, "  CONFIG_{} {}", symbol, change)?;
+            }
+
+            writeln!(f)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Returns the menu path of every symbol in a `.config` file from the comment blocks
+/// `make` writes around each menu (`#`, `# <title>`, `#` and `# end of <title>`).
+fn menus(text: &str) -> HashMap<String, String> {
+    let mut menus = HashMap::new();
+    let mut path: Vec<&str> = Vec::new();
+    let lines = text.lines().collect::<Vec<_>>();
+
+    for (i, line) in lines.iter().enumerate() {
+        if let Some(title) = line.strip_prefix("# end of ") {
+            if let Some(pos) = path.iter().rposition(|menu| *menu == title) {
+                path.truncate(pos);
+            }
+        } else if *line == "#" && lines.get(i + 2) == Some(&"#") {
+            if let Some(title) = lines[i + 1].strip_prefix("# ") {
+                // Comments without a matching end line aren't menus but still label
+                // the options that follow, so replace any previous one at this level.
+                if path.last().is_some_and(|last| !has_end(&lines[i..], last)) {
+                    path.pop();
+                }
+
+                path.push(title);
+            }
+        } else if let Some(symbol) = symbol(line) {
+            menus.insert(symbol.to_string(), path.join(" > "));
+        }
+    }
+
+    menus
+}
+
+/// Reports whether the menu `title` is closed later on.
+fn has_end(lines: &[&str], title: &str) -> bool {
+    let end = format!("# end of {}", title);
+    lines.iter().any(|line| *line == end)
+}
+
+/// Returns the symbol assigned by a `.config` line, if any.
+fn symbol(line: &str) -> Option<&str> {
+    match line.strip_prefix("CONFIG_") {
+        Some(rest) => rest.split_once('=').map(|(symbol, _)| symbol),
+        None => line
+            .strip_prefix("# CONFIG_")
+            .and_then(|rest| rest.strip_suffix(" is not set")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = "\
+#
+# Automatically generated file; DO NOT EDIT.
+# Linux/x86 6.18.1 Kernel Configuration
+#
+CONFIG_CC_VERSION_TEXT=\"gcc (GCC) 14.2.0\"
+
+#
+# General setup
+#
+CONFIG_INIT_ENV_ARG_LIMIT=32
+
+#
+# Timers subsystem
+#
+CONFIG_HZ_PERIODIC=y
+# end of Timers subsystem
+
+CONFIG_CPU_ISOLATION=y
+# end of General setup
+
+#
+# Device Drivers
+#
+
+#
+# Generic Driver Options
+#
+CONFIG_UEVENT_HELPER=y
+
+#
+# Firmware loader
+#
+CONFIG_FW_LOADER=y
+# end of Firmware loader
+# end of Generic Driver Options
+
+#
+# Network device support
+#
+CONFIG_NETDEVICES=y
+
+#
+# Distributed Switch Architecture drivers
+#
+# CONFIG_B53 is not set
+
+#
+# Wireless drivers
+#
+CONFIG_WLAN=y
+# end of Network device support
+
+CONFIG_FIRMWARE_EDID=y
+# end of Device Drivers
+
+CONFIG_TRACE_IRQFLAGS_SUPPORT=y
+";
+
+    #[test]
+    fn nested_menus_and_comments() {
+        let menus = menus(CONFIG);
+
+        for (symbol, path) in [
+            ("CC_VERSION_TEXT", ""),
+            ("INIT_ENV_ARG_LIMIT", "General setup"),
+            ("HZ_PERIODIC", "General setup > Timers subsystem"),
+            ("CPU_ISOLATION", "General setup"),
+            ("UEVENT_HELPER", "Device Drivers > Generic Driver Options"),
+            (
+                "FW_LOADER",
+                "Device Drivers > Generic Driver Options > Firmware loader",
+            ),
+            ("NETDEVICES", "Device Drivers > Network device support"),
+            // Comments have no end line and are replaced by the next one.
+            (
+                "B53",
+                "Device Drivers > Network device support > Distributed Switch Architecture drivers",
+            ),
+            (
+                "WLAN",
+                "Device Drivers > Network device support > Wireless drivers",
+            ),
+            ("FIRMWARE_EDID", "Device Drivers"),
+            ("TRACE_IRQFLAGS_SUPPORT", ""),
+        ] {
+            assert_eq!(
+                menus.get(symbol).map(String::as_str),
+                Some(path),
+                "{}",
+                symbol
+            );
+        }
+
+        assert_eq!(menus.len(), 11);
+    }
+
+    #[test]
+    fn end_lines() {
+        let lines = CONFIG.lines().collect::<Vec<_>>();
+
+        assert!(has_end(&lines, "Timers subsystem"));
+        assert!(!has_end(&lines, "Wireless drivers"));
+    }
+}
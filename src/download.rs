@@ -0,0 +1,149 @@
+use crate::cache::Cache;
+use crate::mirror::{self, Mirrors};
+use crate::verify::{self, Verifier};
+use crate::version::Version;
+
+use anyhow::{bail, Context};
+use std::fs;
+use std::path::PathBuf;
+
+/// Downloads release files of a kernel series through the cache
+/// and verifies them against the series' signed checksum list.
+#[derive(Debug)]
+pub struct Downloader<'a> {
+    mirrors: &'a Mirrors,
+    verifier: &'a Verifier,
+    cache: &'a Cache,
+    sums: String,
+}
+
+impl<'a> Downloader<'a> {
+    /// Fetches and verifies the checksum list covering `version`,
+    /// either from the mirrors or from the URL or path `checksums`.
+    pub fn new(
+        mirrors: &'a Mirrors,
+        verifier: &'a Verifier,
+        cache: &'a Cache,
+        version: &Version,
+        checksums: Option<&str>,
+    ) -> anyhow::Result<Self> {
+        let file = cache.version_dir(version)?.join("sha256sums.asc");
+        match checksums {
+            Some(checksums) => mirror::download(checksums, &file)?,
+            None => mirrors.fetch(&version.checksums_path(), &file)?,
+        }
+
+        let sums = verifier
+            .clearsigned(&file)
+            .context("checksum list verification failed");
+        fs::remove_file(&file)?;
+
+        Ok(Self {
+            mirrors,
+            verifier,
+            cache,
+            sums: sums?,
+        })
+    }
+
+    /// Returns the cache path of the release file at `src` (relative to a mirror)
+    /// belonging to `version`. The file is not downloaded.
+    pub fn path(&self, version: &Version, src: &str) -> anyhow::Result<PathBuf> {
+        let file_name = src.rsplit('/').next().unwrap();
+        let sha256 = match verify::expected_sha256(&self.sums, file_name) {
+            Some(sha256) => sha256,
+            None => bail!("{} is not listed in the checksum list", file_name),
+        };
+
+        let ext = if file_name.starts_with("patch-") {
+            "patch.xz"
+        } else {
+            "tar.xz"
+        };
+
+        self.cache.file(version, sha256, ext)
+    }
+
+    /// Downloads the xz compressed release file at `src` (relative to a mirror) into the cache
+    /// and verifies the detached signature over its decompressed contents.
+    /// The signature is fetched from the mirrors unless the URL or path `signature` is given.
+    pub fn get(
+        &self,
+        version: &Version,
+        src: &str,
+        signature: Option<&str>,
+    ) -> anyhow::Result<PathBuf> {
+        let path = self.path(version, src)?;
+        let sha256 = path.file_name().unwrap().to_string_lossy();
+        let sha256 = sha256.split('.').next().unwrap();
+
+        self.cache.get(self.mirrors, src, &path, sha256)?;
+
+        let mut signature_file = path.clone().into_os_string();
+        signature_file.push(".sign");
+        let signature_file = PathBuf::from(signature_file);
+
+        let signature_src = format!("{}.sign", src.trim_end_matches(".xz"));
+        match signature {
+            Some(signature) => mirror::download(signature, &signature_file)?,
+            None => self.mirrors.fetch(&signature_src, &signature_file)?,
+        }
+
+        let result = verify::signature(self.verifier, &path, &signature_file);
+        fs::remove_file(signature_file)?;
+        result.with_context(|| format!("failed to verify {}", src))?;
+
+        Ok(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use std::path::Path;
+
+    /// Fingerprint of the throwaway key in `tests/fixtures/test-key.gpg`
+    /// that signed the fixture mirror.
+    const TEST_KEY: &str = "3A7F94BCB279A00C63670329F3B34F06E17CF053";
+
+    fn fixtures() -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures")
+    }
+
+    fn download(name: &str, trusted: &[String]) -> anyhow::Result<PathBuf> {
+        let cache_dir = std::env::temp_dir().join(format!(
+            "rustkrazy-download-{}-{}",
+            name,
+            std::process::id()
+        ));
+        let _ = fs::remove_dir_all(&cache_dir);
+
+        let mirror = format!("file://{}", fixtures().join("mirror").display());
+        let mirrors = Mirrors::load(&[mirror], None, 0)?;
+        let verifier = Verifier::new(&fixtures().join("test-key.gpg"), trusted)?;
+        let cache = Cache::new(Some(cache_dir))?;
+        let version = "3.0".parse::<Version>()?;
+
+        let downloader = Downloader::new(&mirrors, &verifier, &cache, &version, None)?;
+        downloader.get(&version, &version.tarball_path(), None)
+    }
+
+    #[test]
+    fn verifies_signed_fixture_offline() {
+        let tarball = download("trusted", &[TEST_KEY.to_string()]).unwrap();
+
+        assert_eq!(
+            verify::sha256(&tarball).unwrap(),
+            "45dfcee3742e6c259d0ba70c5b486e190467408007f6dff3459b0d20c92adea7"
+        );
+    }
+
+    #[test]
+    fn rejects_signature_by_untrusted_key() {
+        // Without --trusted-key only the kernel.org release keys are accepted.
+        let err = download("untrusted", &[]).unwrap_err();
+
+        assert!(format!("{:#}", err).contains("not made by a trusted key"));
+    }
+}
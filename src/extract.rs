@@ -0,0 +1,361 @@
+use anyhow::{bail, Context};
+use flate2::read::GzDecoder;
+use std::collections::HashSet;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, Read};
+use std::path::{Component, Path, PathBuf};
+use tar::{Archive, EntryType};
+use xz2::read::XzDecoder;
+
+const XZ_MAGIC: &[u8] = &[0xfd, b'7', b'z', b'X', b'Z', 0x00];
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+
+/// Unpacks a (possibly xz or gzip compressed) tarball into `to`,
+/// stripping the top-level directory every kernel tarball has.
+///
+/// `to` must not contain any symlinks. Entries with absolute paths or `..` components,
+/// symlinks pointing outside of `to` or through other symlinks and entries
+/// that would be written through a symlink are rejected.
+pub fn unpack(tarball: &Path, to: &Path) -> anyhow::Result<()> {
+    let file = File::open(tarball)?;
+    let total = file.metadata()?.len();
+
+    let mut reader = BufReader::new(Progress::new(file, total));
+    let magic = reader.fill_buf()?;
+
+    let decoder: Box<dyn Read> = if magic.starts_with(XZ_MAGIC) {
+        Box::new(XzDecoder::new(reader))
+    } else if magic.starts_with(GZIP_MAGIC) {
+        Box::new(GzDecoder::new(reader))
+    } else {
+        Box::new(reader)
+    };
+
+    let result = unpack_entries(Archive::new(decoder), &to.canonicalize()?);
+
+    // Terminate the progress line.
+    println!();
+    result
+}
+
+fn unpack_entries(mut archive: Archive<Box<dyn Read>>, root: &Path) -> anyhow::Result<()> {
+    let mut tree = Tree::new();
+
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let entry_type = entry.header().entry_type();
+
+        if entry_type == EntryType::XGlobalHeader {
+            continue;
+        }
+
+        let path = entry.path()?.into_owned();
+        let rel = match strip_top_level(&path)? {
+            Some(rel) => rel,
+            None if entry_type.is_dir() => continue,
+            None => bail!("unexpected file {} at archive root", path.display()),
+        };
+
+        // Check before creating anything so that no directory
+        // is created through a symlink either.
+        if tree.through_link(&rel) {
+            bail!("{} would be written through a symlink", path.display());
+        }
+
+        let dest = root.join(&rel);
+
+        if let Some(parent) = dest.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        match entry_type {
+            EntryType::Directory => fs::create_dir_all(&dest)?,
+            EntryType::Regular | EntryType::Continuous => {
+                entry.unpack(&dest)?;
+            }
+            EntryType::Symlink => {
+                let target = entry.link_name()?.context("symlink without target")?;
+                if !tree.add_link(&rel, &target) {
+                    bail!(
+                        "symlink {} -> {} points outside of the tree or through another symlink",
+                        path.display(),
+                        target.display()
+                    );
+                }
+
+                entry.unpack(&dest)?;
+            }
+            EntryType::Link => {
+                let target = entry.link_name()?.context("hard link without target")?;
+                let target = match strip_top_level(&target)? {
+                    Some(target) => target,
+                    None => bail!("hard link {} to archive root", path.display()),
+                };
+
+                if tree.through_link(&target) {
+                    bail!("hard link {} points through a symlink", path.display());
+                }
+
+                fs::hard_link(root.join(target), &dest)?;
+            }
+            other => bail!("unsupported entry type {:?} for {}", other, path.display()),
+        }
+    }
+
+    Ok(())
+}
+
+/// Removes the first component of an archive path, rejecting unsafe paths.
+/// Returns `None` for the top-level directory itself.
+fn strip_top_level(path: &Path) -> anyhow::Result<Option<PathBuf>> {
+    let mut rel = PathBuf::new();
+
+    for component in path.components() {
+        match component {
+            Component::Normal(part) => rel.push(part),
+            Component::CurDir => {}
+            _ => bail!("unsafe path {} in archive", path.display()),
+        }
+    }
+
+    let rel = rel.components().skip(1).collect::<PathBuf>();
+    Ok(Some(rel).filter(|rel| !rel.as_os_str().is_empty()))
+}
+
+/// The symlinks unpacked so far, used to keep paths from leaving the tree.
+/// The tree is unpacked into a directory without symlinks, so these are all symlinks in it.
+///
+/// Symlink targets are resolved lexically, which is only sound if no path
+/// the kernel resolves passes through a symlink. Entries and link targets
+/// going through a known symlink are rejected, and so are symlinks created
+/// at a path an earlier link target went through.
+struct Tree {
+    /// Paths of the symlinks relative to the tree.
+    links: HashSet<PathBuf>,
+    /// Directories that symlink targets pass through.
+    traversed: HashSet<PathBuf>,
+}
+
+impl Tree {
+    fn new() -> Self {
+        Self {
+            links: HashSet::new(),
+            traversed: HashSet::new(),
+        }
+    }
+
+    /// Reports whether `rel` or one of its parent directories is a symlink.
+    fn through_link(&self, rel: &Path) -> bool {
+        rel.ancestors().any(|path| self.links.contains(path))
+    }
+
+    /// Records a symlink at `rel` pointing to `target` if the target resolves
+    /// to a location inside the tree without passing through another symlink.
+    fn add_link(&mut self, rel: &Path, target: &Path) -> bool {
+        if self.traversed.contains(rel) {
+            return false;
+        }
+
+        let mut resolved = rel.parent().map(Path::to_path_buf).unwrap_or_default();
+        let mut traversed = Vec::new();
+
+        for component in target.components() {
+            // Every component but the last has to be a directory.
+            if self.links.contains(&resolved) {
+                return false;
+            }
+
+            traversed.push(resolved.clone());
+
+            match component {
+                Component::Normal(part) => resolved.push(part),
+                Component::CurDir => {}
+                Component::ParentDir if resolved.pop() => {}
+                _ => return false,
+            }
+        }
+
+        self.traversed.extend(traversed);
+        self.links.insert(rel.to_path_buf());
+        true
+    }
+}
+
+/// Prints the share of a reader that has been consumed in steps of 10%.
+struct Progress<R> {
+    inner: R,
+    read: u64,
+    total: u64,
+    reported: u64,
+}
+
+impl<R> Progress<R> {
+    fn new(inner: R, total: u64) -> Self {
+        Self {
+            inner,
+            read: 0,
+            total,
+            reported: 0,
+        }
+    }
+}
+
+impl<R: Read> Read for Progress<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.read += n as u64;
+
+        if let Some(percent) = (self.read * 100).checked_div(self.total) {
+            let percent = percent / 10 * 10;
+            if percent > self.reported {
+                print!("Unpacking... {}%\r", percent);
+                io::Write::flush(&mut io::stdout())?;
+
+                self.reported = percent;
+            }
+        }
+
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use tar::{Builder, Header};
+
+    enum Entry<'a> {
+        Dir(&'a str),
+        File(&'a str),
+        Symlink(&'a str, &'a str),
+    }
+
+    /// Builds a tarball from `entries` below a `linux/` top-level directory
+    /// and unpacks it into a fresh directory, returning that directory.
+    fn unpack_fixture(name: &str, entries: &[Entry]) -> (PathBuf, anyhow::Result<()>) {
+        let dir =
+            std::env::temp_dir().join(format!("rustkrazy-extract-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("tree")).unwrap();
+
+        let mut builder = Builder::new(Vec::new());
+        for entry in entries {
+            let mut header = Header::new_gnu();
+            header.set_mode(0o755);
+
+            match entry {
+                Entry::Dir(path) => {
+                    header.set_entry_type(EntryType::Directory);
+                    header.set_size(0);
+                    builder
+                        .append_data(&mut header, format!("linux/{}", path), io::empty())
+                        .unwrap();
+                }
+                Entry::File(path) => {
+                    header.set_size(4);
+                    builder
+                        .append_data(&mut header, format!("linux/{}", path), &b"data"[..])
+                        .unwrap();
+                }
+                Entry::Symlink(path, target) => {
+                    header.set_entry_type(EntryType::Symlink);
+                    header.set_size(0);
+                    builder
+                        .append_link(&mut header, format!("linux/{}", path), target)
+                        .unwrap();
+                }
+            }
+        }
+
+        let tarball = dir.join("linux.tar");
+        fs::write(&tarball, builder.into_inner().unwrap()).unwrap();
+
+        let result = unpack(&tarball, &dir.join("tree"));
+        (dir, result)
+    }
+
+    #[test]
+    fn unpacks_links_inside_the_tree() {
+        let (dir, result) = unpack_fixture(
+            "inside",
+            &[
+                Entry::Dir("arch"),
+                Entry::File("arch/file"),
+                Entry::Symlink("scripts/link", "../arch/file"),
+                Entry::Symlink("arch/self", "."),
+                Entry::Symlink("chain", "scripts/link"),
+            ],
+        );
+
+        result.unwrap();
+        assert_eq!(fs::read(dir.join("tree/chain")).unwrap(), b"data");
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn rejects_link_outside_the_tree() {
+        let (dir, result) = unpack_fixture("outside", &[Entry::Symlink("a/link", "../../x")]);
+
+        assert!(result.is_err());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn rejects_absolute_link() {
+        let (dir, result) = unpack_fixture("absolute", &[Entry::Symlink("link", "/etc")]);
+
+        assert!(result.is_err());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn rejects_link_through_link() {
+        let (dir, result) = unpack_fixture(
+            "through",
+            &[Entry::Symlink("d", "."), Entry::Symlink("x", "d/..")],
+        );
+
+        assert!(result.is_err());
+        assert!(fs::symlink_metadata(dir.join("tree/x")).is_err());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn rejects_link_created_on_traversed_path() {
+        let (dir, result) = unpack_fixture(
+            "traversed",
+            &[Entry::Symlink("x", "d/.."), Entry::Symlink("d", ".")],
+        );
+
+        assert!(result.is_err());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn rejects_entries_written_through_link() {
+        let (dir, result) = unpack_fixture(
+            "written",
+            &[
+                Entry::Dir("sub"),
+                Entry::Symlink("x", "sub"),
+                Entry::File("x/escaped/file"),
+            ],
+        );
+
+        assert!(result.is_err());
+        assert!(!dir.join("tree/sub/escaped").exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn rejects_parent_components() {
+        assert!(strip_top_level(Path::new("linux/../x")).is_err());
+        assert!(strip_top_level(Path::new("/linux/x")).is_err());
+        assert_eq!(
+            strip_top_level(Path::new("./linux/a/b")).unwrap(),
+            Some(PathBuf::from("a/b"))
+        );
+        assert_eq!(strip_top_level(Path::new("linux/")).unwrap(), None);
+    }
+}
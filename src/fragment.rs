@@ -0,0 +1,334 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// A single option assignment in a config fragment.
+#[derive(Debug)]
+pub struct Assignment {
+    /// 1-based line number.
+    pub line: usize,
+    /// Symbol name without the `CONFIG_` prefix.
+    pub symbol: String,
+    /// `y`, `m`, `n` (for `# CONFIG_X is not set`), a quoted string,
+    /// a decimal integer or a hex value.
+    pub value: String,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+
+/// A problem found in a config fragment.
+#[derive(Debug)]
+pub struct Diagnostic {
+    pub path: PathBuf,
+    pub line: usize,
+    pub severity: Severity,
+    pub message: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let severity = match self.severity {
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        };
+
+        write!(
+            f,
+            "{}:{}: {}: {}",
+            self.path.display(),
+            self.line,
+            severity,
+            self.message
+        )
+    }
+}
+
+/// Parses Kconfig fragment syntax as found in `.config` files:
+/// `CONFIG_X=y|m|n`, `CONFIG_X="string"`, `CONFIG_X=123`, `CONFIG_X=0x1f`,
+/// `# CONFIG_X is not set`, comments and blank lines.
+///
+/// Malformed lines are reported as errors, repeated assignments to the same symbol
+/// as warnings (same value) or errors (different value).
+pub fn parse(path: &Path, text: &str) -> (Vec<Assignment>, Vec<Diagnostic>) {
+    let mut assignments = Vec::new();
+    let mut diagnostics = Vec::new();
+    let mut seen: HashMap<String, usize> = HashMap::new();
+
+    for (i, line) in text.lines().enumerate() {
+        let line_no = i + 1;
+
+        let diagnostic = |severity, message| Diagnostic {
+            path: path.to_path_buf(),
+            line: line_no,
+            severity,
+            message,
+        };
+
+        let assignment = match parse_line(line) {
+            Ok(Some((symbol, value))) => Assignment {
+                line: line_no,
+                symbol,
+                value,
+            },
+            Ok(None) => continue,
+            Err(message) => {
+                diagnostics.push(diagnostic(Severity::Error, message));
+                continue;
+            }
+        };
+
+        if let Some(&previous) = seen.get(&assignment.symbol) {
+            let previous: &Assignment = &assignments[previous];
+
+            if previous.value == assignment.value {
+                diagnostics.push(diagnostic(
+                    Severity::Warning,
+                    format!(
+                        "duplicate assignment CONFIG_{}={} (first on line {})",
+                        assignment.symbol, assignment.value, previous.line
+                    ),
+                ));
+            } else {
+                diagnostics.push(diagnostic(
+                    Severity::Error,
+                    format!(
+                        "contradictory assignment CONFIG_{}={} (set to {} on line {})",
+                        assignment.symbol, assignment.value, previous.value, previous.line
+                    ),
+                ));
+            }
+        }
+
+        seen.insert(assignment.symbol.clone(), assignments.len());
+        assignments.push(assignment);
+    }
+
+    (assignments, diagnostics)
+}
+
+/// Parses a `CONFIG_X=value` override given on the command line.
+/// The `CONFIG_` prefix is optional.
+pub fn parse_set(s: &str) -> Result<(String, String), String> {
+    let line = if s.starts_with("CONFIG_") {
+        s.to_string()
+    } else {
+        format!("CONFIG_{}", s)
+    };
+
+    parse_line(&line)?.ok_or_else(|| format!("expected CONFIG_<SYMBOL>=<value>, got {:?}", s))
+}
+
+/// Parses a `CONFIG_X` symbol to unset given on the command line.
+/// The `CONFIG_` prefix is optional.
+pub fn parse_unset(s: &str) -> Result<String, String> {
+    let symbol = s.strip_prefix("CONFIG_").unwrap_or(s);
+
+    if !is_symbol(symbol) {
+        return Err(format!("invalid symbol name {:?}", symbol));
+    }
+
+    Ok(symbol.to_string())
+}
+
+/// Parses a single line, returning the assignment it makes if any.
+fn parse_line(line: &str) -> Result<Option<(String, String)>, String> {
+    let line = line.trim_end();
+
+    if line.is_empty() {
+        return Ok(None);
+    }
+
+    if let Some(comment) = line.strip_prefix('#') {
+        return match comment
+            .strip_prefix(" CONFIG_")
+            .and_then(|rest| rest.strip_suffix(" is not set"))
+        {
+            Some(symbol) if is_symbol(symbol) => Ok(Some((symbol.to_string(), "n".to_string()))),
+            Some(symbol) => Err(format!("invalid symbol name {:?}", symbol)),
+            None => Ok(None),
+        };
+    }
+
+    if line.starts_with(char::is_whitespace) {
+        return Err("leading whitespace".to_string());
+    }
+
+    let rest = match line.strip_prefix("CONFIG_") {
+        Some(rest) => rest,
+        None => return Err(format!("expected CONFIG_<SYMBOL>=<value>, got {:?}", line)),
+    };
+
+    let (symbol, value) = match rest.split_once('=') {
+        Some(assignment) => assignment,
+        None => return Err(format!("missing '=' in {:?}", line)),
+    };
+
+    if !is_symbol(symbol) {
+        return Err(format!("invalid symbol name {:?}", symbol));
+    }
+
+    if !is_value(value) {
+        return Err(format!("invalid value {:?} for CONFIG_{}", value, symbol));
+    }
+
+    Ok(Some((symbol.to_string(), value.to_string())))
+}
+
+fn is_symbol(symbol: &str) -> bool {
+    !symbol.is_empty()
+        && symbol
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+fn is_value(value: &str) -> bool {
+    match value {
+        "y" | "m" | "n" => true,
+        _ if value.starts_with('"') => is_string(value),
+        _ => {
+            let (digits, radix) = match value
+                .strip_prefix("0x")
+                .or_else(|| value.strip_prefix("0X"))
+            {
+                Some(hex) => (hex, 16),
+                None => (value.strip_prefix('-').unwrap_or(value), 10),
+            };
+
+            !digits.is_empty() && digits.chars().all(|c| c.is_digit(radix))
+        }
+    }
+}
+
+/// Checks for a double quoted string with `\` escapes and nothing after the closing quote.
+fn is_string(value: &str) -> bool {
+    let mut chars = value[1..].chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            // Skip the escaped character.
+            '\\' if chars.next().is_none() => return false,
+            '"' => return chars.next().is_none(),
+            _ => {}
+        }
+    }
+
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_ok(text: &str) -> Vec<(String, String)> {
+        let (assignments, diagnostics) = parse(Path::new("test.config"), text);
+        assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+
+        assignments
+            .into_iter()
+            .map(|assignment| (assignment.symbol, assignment.value))
+            .collect()
+    }
+
+    fn parse_err(line: &str) -> Diagnostic {
+        let (_, mut diagnostics) = parse(Path::new("test.config"), line);
+        assert_eq!(diagnostics.len(), 1, "{:?}", diagnostics);
+
+        diagnostics.pop().unwrap()
+    }
+
+    #[test]
+    fn parses_values() {
+        let text = r#"
+# A comment
+CONFIG_A=y
+CONFIG_B=m
+CONFIG_C="plain"
+CONFIG_D="with \"quotes\" and \\ backslash"
+CONFIG_E=0x1f
+CONFIG_F=-42
+# CONFIG_G is not set
+"#;
+
+        let values = parse_ok(text)
+            .into_iter()
+            .map(|(symbol, value)| format!("{}={}", symbol, value))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            values,
+            [
+                "A=y",
+                "B=m",
+                "C=\"plain\"",
+                r#"D="with \"quotes\" and \\ backslash""#,
+                "E=0x1f",
+                "F=-42",
+                "G=n",
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_values() {
+        for line in [
+            r#"CONFIG_A="unterminated"#,
+            r#"CONFIG_A="escaped end\""#,
+            r#"CONFIG_A="trailing" x"#,
+            "CONFIG_A=0xg",
+            "CONFIG_A=-",
+            "CONFIG_A=yes",
+            "CONFIG_A",
+            "CONFIG_A-B=y",
+            " CONFIG_A=y",
+            "A=y",
+            "# CONFIG_A-B is not set",
+        ] {
+            assert_eq!(parse_err(line).severity, Severity::Error, "{}", line);
+        }
+    }
+
+    #[test]
+    fn reports_repeated_assignments() {
+        let (assignments, diagnostics) = parse(
+            Path::new("test.config"),
+            "CONFIG_A=y\nCONFIG_B=y\nCONFIG_A=y\n# CONFIG_B is not set\n",
+        );
+
+        assert_eq!(assignments.len(), 4);
+        assert_eq!(diagnostics.len(), 2);
+
+        assert_eq!(diagnostics[0].severity, Severity::Warning);
+        assert_eq!(diagnostics[0].line, 3);
+        assert_eq!(
+            diagnostics[0].to_string(),
+            "test.config:3: warning: duplicate assignment CONFIG_A=y (first on line 1)"
+        );
+
+        assert_eq!(diagnostics[1].severity, Severity::Error);
+        assert_eq!(
+            diagnostics[1].message,
+            "contradictory assignment CONFIG_B=n (set to y on line 2)"
+        );
+    }
+
+    #[test]
+    fn parses_command_line_overrides() {
+        assert_eq!(
+            parse_set("FOO=y").unwrap(),
+            ("FOO".to_string(), "y".to_string())
+        );
+        assert_eq!(
+            parse_set("CONFIG_BAR=\"x\"").unwrap(),
+            ("BAR".to_string(), "\"x\"".to_string())
+        );
+        assert!(parse_set("FOO").is_err());
+        assert!(parse_set("# CONFIG_FOO is not set").is_err());
+
+        assert_eq!(parse_unset("CONFIG_FOO").unwrap(), "FOO");
+        assert!(parse_unset("FOO=y").is_err());
+    }
+}
@@ -0,0 +1,1147 @@
+use crate::config::Config;
+
+use anyhow::{bail, Context};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// A Kconfig dependency expression.
+#[derive(Clone, Debug)]
+pub enum Expr {
+    /// A symbol or constant (`y`, `m`, `n`, numbers, strings).
+    Sym(String),
+    Not(Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Cmp(String, String, String),
+}
+
+impl Expr {
+    /// Splits a conjunction into its terms.
+    fn conjuncts(&self) -> Vec<&Expr> {
+        match self {
+            Expr::And(a, b) => {
+                let mut terms = a.conjuncts();
+                terms.extend(b.conjuncts());
+                terms
+            }
+            _ => vec![self],
+        }
+    }
+
+    /// Collects the names of all symbols referenced by the expression.
+    fn symbols<'a>(&'a self, out: &mut Vec<&'a str>) {
+        match self {
+            Expr::Sym(name) => out.push(name),
+            Expr::Not(e) => e.symbols(out),
+            Expr::And(a, b) | Expr::Or(a, b) => {
+                a.symbols(out);
+                b.symbols(out);
+            }
+            Expr::Cmp(_, a, b) => {
+                out.push(a);
+                out.push(b);
+            }
+        }
+    }
+
+    /// Collects the symbols that help satisfy the expression when enabled,
+    /// i.e. those not under a negation or in a comparison.
+    fn positive_symbols<'a>(&'a self, out: &mut Vec<&'a str>) {
+        match self {
+            Expr::Sym(name) if !is_constant(name) => out.push(name),
+            Expr::And(a, b) | Expr::Or(a, b) => {
+                a.positive_symbols(out);
+                b.positive_symbols(out);
+            }
+            _ => {}
+        }
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Expr::Sym(name) => write!(f, "{}", name),
+            Expr::Not(e) => match **e {
+                Expr::Sym(_) => write!(f, "!{}", e),
+                _ => write!(f, "!({})", e),
+            },
+            Expr::And(a, b) => {
+                for (i, e) in [a, b].into_iter().enumerate() {
+                    if i > 0 {
+                        write!(f, " && ")?;
+                    }
+
+                    match **e {
+                        Expr::Or(..) => write!(f, "({})", e)?,
+                        _ => write!(f, "{}", e)?,
+                    }
+                }
+
+                Ok(())
+            }
+            Expr::Or(a, b) => write!(f, "{} || {}", a, b),
+            Expr::Cmp(op, a, b) => write!(f, "{}{}{}", a, op, b),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Type {
+    Bool,
+    Tristate,
+    String,
+    Int,
+    Hex,
+}
+
+/// One `config` or `menuconfig` entry of a symbol. Symbols may be defined more than once.
+#[derive(Debug)]
+struct Definition {
+    file: PathBuf,
+    line: usize,
+    /// Own `depends on` expressions and those inherited from enclosing menus, ifs and choices.
+    depends: Vec<Expr>,
+    /// Condition of the prompt, `None` if the entry has no prompt.
+    prompt: Option<Expr>,
+    /// `default` and `def_bool` values in order along with their conditions.
+    defaults: Vec<(Expr, Option<Expr>)>,
+}
+
+#[derive(Debug, Default)]
+struct Symbol {
+    ty: Option<Type>,
+    definitions: Vec<Definition>,
+    /// Symbols selecting this one along with the select condition.
+    selected_by: Vec<(String, Option<Expr>)>,
+    /// Index into `Kconfig::choices`.
+    choice: Option<usize>,
+}
+
+/// The symbols and dependencies of a kernel source tree's Kconfig files.
+#[derive(Debug, Default)]
+pub struct Kconfig {
+    symbols: HashMap<String, Symbol>,
+    choices: Vec<Vec<String>>,
+}
+
+/// An enclosing `if`, `menu` or `choice` block.
+#[derive(Debug)]
+struct Block {
+    kind: String,
+    depends: Vec<Expr>,
+    choice: Option<usize>,
+}
+
+/// The entry attribute lines currently apply to.
+#[derive(Debug)]
+enum Current {
+    None,
+    Config(String),
+    Block,
+}
+
+struct Parser<'a> {
+    srctree: &'a Path,
+    srcarch: &'a str,
+    kconfig: Kconfig,
+    blocks: Vec<Block>,
+    current: Current,
+}
+
+impl Kconfig {
+    /// Parses the Kconfig files of the kernel tree at `srctree`
+    /// starting at the top-level `Kconfig`. `srcarch` is the name of the
+    /// directory below `arch/`, e.g. `x86` or `arm64`.
+    pub fn parse(srctree: &Path, srcarch: &str) -> anyhow::Result<Self> {
+        let mut parser = Parser {
+            srctree,
+            srcarch,
+            kconfig: Kconfig::default(),
+            blocks: Vec::new(),
+            current: Current::None,
+        };
+
+        parser.parse_file(&srctree.join("Kconfig"))?;
+
+        Ok(parser.kconfig)
+    }
+
+    /// Reports whether the tree defines `symbol`.
+    pub fn defines(&self, symbol: &str) -> bool {
+        self.symbols
+            .get(symbol)
+            .is_some_and(|symbol| !symbol.definitions.is_empty())
+    }
+
+    /// Returns `roots` along with all symbols they transitively depend on.
+    pub fn dependencies<'a>(
+        &'a self,
+        roots: impl IntoIterator<Item = &'a str>,
+    ) -> HashSet<&'a str> {
+        let mut closure = HashSet::new();
+        let mut queue = roots.into_iter().collect::<Vec<_>>();
+
+        while let Some(name) = queue.pop() {
+            if !closure.insert(name) {
+                continue;
+            }
+
+            let Some(symbol) = self.symbols.get(name) else {
+                continue;
+            };
+
+            for definition in &symbol.definitions {
+                for expr in &definition.depends {
+                    expr.positive_symbols(&mut queue);
+                }
+            }
+        }
+
+        closure
+    }
+
+    /// Explains the state of `symbol` (without the `CONFIG_` prefix) in `config`,
+    /// following unmet dependencies of other symbols up to `depth` levels deep.
+    pub fn explain(&self, config: &Config, symbol: &str, depth: usize) -> String {
+        let mut out = String::new();
+        let mut visited = HashSet::new();
+
+        self.explain_symbol(config, symbol, depth, 0, &mut visited, &mut out);
+        out
+    }
+
+    fn explain_symbol(
+        &self,
+        config: &Config,
+        name: &str,
+        depth: usize,
+        level: usize,
+        visited: &mut HashSet<String>,
+        out: &mut String,
+    ) {
+        let indent = "  ".repeat(level);
+        let symbol = match self.symbols.get(name) {
+            Some(symbol) if !symbol.definitions.is_empty() => symbol,
+            _ => {
+                *out += &format!(
+                    "{}CONFIG_{} does not exist in this kernel tree (removed or misspelled)\n",
+                    indent, name
+                );
+                return;
+            }
+        };
+
+        match config.get(name) {
+            Some(value) if value != "n" => {
+                *out += &format!("{}CONFIG_{}={}\n", indent, name, value)
+            }
+            _ => *out += &format!("{}CONFIG_{} is not set\n", indent, name),
+        }
+
+        if !visited.insert(name.to_string()) {
+            *out += &format!("{}  (see above)\n", indent);
+            return;
+        }
+
+        let indent = "  ".repeat(level + 1);
+
+        for definition in &symbol.definitions {
+            if symbol.definitions.len() > 1 {
+                *out += &format!(
+                    "{}defined at {}:{}\n",
+                    indent,
+                    definition.file.display(),
+                    definition.line
+                );
+            }
+
+            let mut limited = false;
+            for expr in &definition.depends {
+                for term in expr.conjuncts() {
+                    let value = self.eval(config, term);
+                    if value == 2 {
+                        continue;
+                    }
+
+                    limited = true;
+                    *out += &format!(
+                        "{}depends on {} [{}]{}\n",
+                        indent,
+                        term,
+                        tristate(value),
+                        self.values(config, term)
+                    );
+
+                    if depth > 0 {
+                        for dep in self.blocking_symbols(config, term) {
+                            self.explain_symbol(config, dep, depth - 1, level + 2, visited, out);
+                        }
+                    }
+                }
+            }
+
+            if !limited {
+                *out += &format!("{}dependencies are met\n", indent);
+            }
+
+            match &definition.prompt {
+                None if definition.defaults.is_empty() => {
+                    *out += &format!("{}has no prompt, it can only be selected\n", indent)
+                }
+                None => {
+                    *out += &format!(
+                        "{}has no prompt, it is set by its defaults or selected\n",
+                        indent
+                    )
+                }
+                Some(cond) if self.eval(config, cond) == 0 => {
+                    *out += &format!(
+                        "{}prompt is hidden by \"if {}\"{}\n",
+                        indent,
+                        cond,
+                        self.values(config, cond)
+                    )
+                }
+                Some(_) => {}
+            }
+
+            // The first default with a met condition applies.
+            let mut applied = false;
+            for (value, cond) in &definition.defaults {
+                let active = cond.as_ref().is_none_or(|cond| self.eval(config, cond) > 0);
+
+                let state = match (active, applied) {
+                    (false, _) => "inactive",
+                    (true, false) => "active",
+                    (true, true) => "overridden by an earlier default",
+                };
+                applied |= active;
+
+                let cond = match cond {
+                    Some(cond) => format!(" if {}", cond),
+                    None => String::new(),
+                };
+
+                *out += &format!("{}default {}{} [{}]\n", indent, value, cond, state);
+            }
+        }
+
+        for (selector, cond) in &symbol.selected_by {
+            let active = config.get(selector).is_some_and(|value| value != "n")
+                && cond.as_ref().is_none_or(|cond| self.eval(config, cond) > 0);
+
+            let cond = match cond {
+                Some(cond) => format!(" if {}", cond),
+                None => String::new(),
+            };
+
+            *out += &format!(
+                "{}selected by {}{} [{}]\n",
+                indent,
+                selector,
+                cond,
+                if active { "active" } else { "inactive" }
+            );
+        }
+
+        if let Some(choice) = symbol.choice {
+            let chosen = self.chosen(config, choice);
+            if let Some(chosen) = chosen.filter(|chosen| *chosen != name) {
+                *out += &format!(
+                    "{}is part of a choice, CONFIG_{} is selected instead\n",
+                    indent, chosen
+                );
+            }
+        }
+    }
+
+    fn chosen<'a>(&'a self, config: &Config, choice: usize) -> Option<&'a str> {
+        self.choices[choice]
+            .iter()
+            .find(|member| config.get(member) == Some("y"))
+            .map(String::as_str)
+    }
+
+    /// Returns the symbols of a failing dependency term worth explaining further:
+    /// plain symbols and symbol comparisons, but not alternatives.
+    fn blocking_symbols<'a>(&self, config: &Config, term: &'a Expr) -> Vec<&'a str> {
+        match term {
+            Expr::Sym(name) if !is_constant(name) => vec![name],
+            Expr::Cmp(_, a, _) if !is_constant(a) => vec![a],
+            Expr::And(..) => term
+                .conjuncts()
+                .into_iter()
+                .filter(|term| self.eval(config, term) < 2)
+                .flat_map(|term| self.blocking_symbols(config, term))
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Lists the current values of the symbols in a compound expression.
+    fn values(&self, config: &Config, expr: &Expr) -> String {
+        if let Expr::Sym(_) = expr {
+            return String::new();
+        }
+
+        let mut symbols = Vec::new();
+        expr.symbols(&mut symbols);
+        let mut seen = HashSet::new();
+        symbols.retain(|symbol| !is_constant(symbol) && seen.insert(*symbol));
+
+        if symbols.is_empty() {
+            return String::new();
+        }
+
+        let values = symbols
+            .iter()
+            .map(|symbol| format!("{}={}", symbol, self.value(config, symbol)))
+            .collect::<Vec<_>>();
+
+        format!(" where {}", values.join(", "))
+    }
+
+    /// Returns the value of a symbol or constant as a string.
+    fn value(&self, config: &Config, name: &str) -> String {
+        match self.symbols.get(name) {
+            Some(symbol) => match (config.get(name), symbol.ty) {
+                (Some(value), _) => value.trim_matches('"').to_string(),
+                (None, Some(Type::String | Type::Int | Type::Hex)) => String::new(),
+                (None, _) => "n".to_string(),
+            },
+            None if is_constant(name) => name.trim_matches('"').to_string(),
+            // Undefined symbols are treated as `n`.
+            None => "n".to_string(),
+        }
+    }
+
+    /// Evaluates an expression to a tristate value (0 = n, 1 = m, 2 = y).
+    fn eval(&self, config: &Config, expr: &Expr) -> u8 {
+        match expr {
+            Expr::Sym(name) => {
+                if name.starts_with("$(") {
+                    // Macros (e.g. compiler checks) can't be evaluated here, assume they pass.
+                    return 2;
+                }
+
+                let is_tristate = self.symbols.get(name).is_none_or(|symbol| {
+                    matches!(symbol.ty, None | Some(Type::Bool | Type::Tristate))
+                });
+
+                match (is_tristate, self.value(config, name).as_str()) {
+                    (true, "y") => 2,
+                    (true, "m") => 1,
+                    _ => 0,
+                }
+            }
+            Expr::Not(e) => 2 - self.eval(config, e),
+            Expr::And(a, b) => self.eval(config, a).min(self.eval(config, b)),
+            Expr::Or(a, b) => self.eval(config, a).max(self.eval(config, b)),
+            Expr::Cmp(op, a, b) => {
+                let a = self.value(config, a);
+                let b = self.value(config, b);
+
+                let ordering = match (parse_number(&a), parse_number(&b)) {
+                    (Some(a), Some(b)) => a.cmp(&b),
+                    _ => a.cmp(&b),
+                };
+
+                let result = match op.as_str() {
+                    "=" => ordering.is_eq(),
+                    "!=" => ordering.is_ne(),
+                    "<" => ordering.is_lt(),
+                    "<=" => ordering.is_le(),
+                    ">" => ordering.is_gt(),
+                    ">=" => ordering.is_ge(),
+                    _ => false,
+                };
+
+                if result {
+                    2
+                } else {
+                    0
+                }
+            }
+        }
+    }
+}
+
+impl Parser<'_> {
+    fn parse_file(&mut self, path: &Path) -> anyhow::Result<()> {
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+
+        let rel = path
+            .strip_prefix(self.srctree)
+            .unwrap_or(path)
+            .to_path_buf();
+        let mut lines = text.lines().enumerate().peekable();
+
+        while let Some((i, line)) = lines.next() {
+            let line_no = i + 1;
+
+            // Join continuation lines.
+            let mut line = line.to_string();
+            while line.ends_with('\\') {
+                line.pop();
+                match lines.next() {
+                    Some((_, next)) => line += next,
+                    None => break,
+                }
+            }
+
+            let tokens = tokenize(&line)
+                .with_context(|| format!("{}:{}: invalid line", rel.display(), line_no))?;
+
+            let keyword = match tokens.first() {
+                Some(keyword) => keyword.as_str(),
+                None => continue,
+            };
+
+            if keyword == "help" || keyword == "---help---" {
+                skip_help(&mut lines);
+                continue;
+            }
+
+            self.parse_line(path, &rel, line_no, &tokens)
+                .with_context(|| format!("{}:{}", rel.display(), line_no))?;
+        }
+
+        Ok(())
+    }
+
+    fn parse_line(
+        &mut self,
+        path: &Path,
+        rel: &Path,
+        line: usize,
+        tokens: &[String],
+    ) -> anyhow::Result<()> {
+        let keyword = tokens[0].as_str();
+        let args = &tokens[1..];
+
+        match keyword {
+            "config" | "menuconfig" => {
+                let name = args.first().context("missing symbol name")?.clone();
+
+                let depends = self
+                    .blocks
+                    .iter()
+                    .flat_map(|block| block.depends.iter().cloned())
+                    .collect();
+                let choice = self.blocks.iter().rev().find_map(|block| block.choice);
+
+                let symbol = self.kconfig.symbols.entry(name.clone()).or_default();
+                symbol.definitions.push(Definition {
+                    file: rel.to_path_buf(),
+                    line,
+                    depends,
+                    prompt: None,
+                    defaults: Vec::new(),
+                });
+
+                if let Some(choice) = choice {
+                    symbol.choice = Some(choice);
+                    self.kconfig.choices[choice].push(name.clone());
+                }
+
+                self.current = Current::Config(name);
+            }
+            "menu" | "if" | "choice" => {
+                let depends = match keyword {
+                    "if" => vec![parse_expr(args)?],
+                    _ => Vec::new(),
+                };
+
+                let choice = (keyword == "choice").then(|| {
+                    self.kconfig.choices.push(Vec::new());
+                    self.kconfig.choices.len() - 1
+                });
+
+                self.blocks.push(Block {
+                    kind: keyword.to_string(),
+                    depends,
+                    choice,
+                });
+                self.current = Current::Block;
+            }
+            "endmenu" | "endif" | "endchoice" => {
+                let expected = &keyword[3..];
+                match self.blocks.pop() {
+                    Some(block) if block.kind == expected => {}
+                    _ => bail!("unexpected {}", keyword),
+                }
+
+                self.current = Current::None;
+            }
+            "comment" | "mainmenu" => self.current = Current::None,
+            "source" | "rsource" | "osource" | "orsource" => {
+                let file = args.first().context("missing file name")?;
+                let file = file
+                    .trim_matches('"')
+                    .replace("$(SRCARCH)", self.srcarch)
+                    .replace("$(HEADER_ARCH)", self.srcarch);
+
+                let base = if keyword.starts_with('r') || keyword.starts_with("or") {
+                    path.parent().unwrap_or(self.srctree)
+                } else {
+                    self.srctree
+                };
+
+                let optional = keyword.starts_with('o');
+                let files = expand_glob(&base.join(&file))?;
+
+                if files.is_empty() && !optional {
+                    bail!("sourced file {} not found", file);
+                }
+
+                let current = std::mem::replace(&mut self.current, Current::None);
+                for file in files {
+                    self.parse_file(&file)?;
+                }
+                self.current = current;
+            }
+            _ if args
+                .first()
+                .is_some_and(|op| op == ":=" || op == "=" || op == "+=") =>
+            {
+                // Macro variable assignment.
+            }
+            _ => self.parse_attribute(keyword, args)?,
+        }
+
+        Ok(())
+    }
+
+    fn parse_attribute(&mut self, keyword: &str, args: &[String]) -> anyhow::Result<()> {
+        let (ty, has_prompt) = match keyword {
+            "bool" | "boolean" => (Some(Type::Bool), !args.is_empty()),
+            "tristate" => (Some(Type::Tristate), !args.is_empty()),
+            "string" => (Some(Type::String), !args.is_empty()),
+            "int" => (Some(Type::Int), !args.is_empty()),
+            "hex" => (Some(Type::Hex), !args.is_empty()),
+            "def_bool" => (Some(Type::Bool), false),
+            "def_tristate" => (Some(Type::Tristate), false),
+            "prompt" => (None, true),
+            _ => (None, false),
+        };
+
+        if has_prompt {
+            let cond = condition(args)?.unwrap_or_else(|| Expr::Sym("y".to_string()));
+
+            if let Current::Config(name) = &self.current {
+                let symbol = self.kconfig.symbols.get_mut(name).unwrap();
+                symbol.definitions.last_mut().unwrap().prompt = Some(cond);
+            }
+        }
+
+        if ty.is_some() {
+            if let Current::Config(name) = &self.current {
+                self.kconfig.symbols.get_mut(name).unwrap().ty = ty;
+            }
+
+            if !keyword.starts_with("def_") {
+                return Ok(());
+            }
+        }
+
+        match keyword {
+            "default" | "def_bool" | "def_tristate" => {
+                // Defaults of choices pick a member and are ignored.
+                if let Current::Config(name) = &self.current {
+                    let value = parse_expr(args)?;
+                    let cond = condition(args)?;
+
+                    let symbol = self.kconfig.symbols.get_mut(name).unwrap();
+                    symbol
+                        .definitions
+                        .last_mut()
+                        .unwrap()
+                        .defaults
+                        .push((value, cond));
+                }
+            }
+            "depends" => {
+                let expr = match args.first().map(String::as_str) {
+                    Some("on") => parse_expr(&args[1..])?,
+                    _ => bail!("expected \"depends on\""),
+                };
+
+                match &self.current {
+                    Current::Config(name) => {
+                        let symbol = self.kconfig.symbols.get_mut(name).unwrap();
+                        symbol.definitions.last_mut().unwrap().depends.push(expr);
+                    }
+                    Current::Block => {
+                        if let Some(block) = self.blocks.last_mut() {
+                            block.depends.push(expr);
+                        }
+                    }
+                    Current::None => {}
+                }
+            }
+            "select" => {
+                if let Current::Config(name) = &self.current {
+                    let target = args.first().context("missing select target")?;
+                    let cond = condition(args)?;
+
+                    self.kconfig
+                        .symbols
+                        .entry(target.clone())
+                        .or_default()
+                        .selected_by
+                        .push((name.clone(), cond));
+                }
+            }
+            // Attributes that don't affect whether a symbol can be enabled.
+            _ => {}
+        }
+
+        Ok(())
+    }
+}
+
+/// Skips an indented help text.
+fn skip_help<'a>(lines: &mut std::iter::Peekable<impl Iterator<Item = (usize, &'a str)>>) {
+    let mut indent = None;
+
+    while let Some((_, line)) = lines.peek() {
+        if line.trim().is_empty() {
+            lines.next();
+            continue;
+        }
+
+        let line_indent = line.len() - line.trim_start().len();
+        let expanded = line[..line_indent].replace('\t', "        ").len();
+
+        match indent {
+            None if line_indent > 0 => indent = Some(expanded),
+            Some(indent) if expanded >= indent => {}
+            _ => break,
+        }
+
+        lines.next();
+    }
+}
+
+/// Splits a Kconfig line into words, quoted strings, `$(...)` macros and operators,
+/// dropping comments.
+fn tokenize(line: &str) -> anyhow::Result<Vec<String>> {
+    let mut tokens = Vec::new();
+    let mut chars = line.char_indices().peekable();
+
+    while let Some(&(start, c)) = chars.peek() {
+        match c {
+            _ if c.is_whitespace() => {
+                chars.next();
+            }
+            '#' => break,
+            '"' | '\'' => {
+                chars.next();
+                let mut token = String::from('"');
+                let mut closed = false;
+
+                while let Some((_, ch)) = chars.next() {
+                    match ch {
+                        '\\' => {
+                            if let Some((_, escaped)) = chars.next() {
+                                token.push(escaped);
+                            }
+                        }
+                        _ if ch == c => {
+                            closed = true;
+                            break;
+                        }
+                        _ => token.push(ch),
+                    }
+                }
+
+                if !closed {
+                    bail!("unterminated string");
+                }
+
+                token.push('"');
+                tokens.push(token);
+            }
+            '$' if line[start..].starts_with("$(") => {
+                let mut level = 0;
+                let mut end = line.len();
+
+                for (i, ch) in line[start..].char_indices() {
+                    match ch {
+                        '(' => level += 1,
+                        ')' => {
+                            level -= 1;
+                            if level == 0 {
+                                end = start + i + 1;
+                                break;
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+
+                tokens.push(line[start..end].to_string());
+                while chars.peek().is_some_and(|&(i, _)| i < end) {
+                    chars.next();
+                }
+            }
+            '(' | ')' => {
+                chars.next();
+                tokens.push(c.to_string());
+            }
+            '!' | '=' | '<' | '>' | '&' | '|' | ':' | '+' => {
+                chars.next();
+                let mut token = c.to_string();
+
+                if let Some(&(_, next)) = chars.peek() {
+                    if matches!(
+                        (c, next),
+                        ('!', '=')
+                            | ('<', '=')
+                            | ('>', '=')
+                            | ('&', '&')
+                            | ('|', '|')
+                            | (':', '=')
+                            | ('+', '=')
+                    ) {
+                        token.push(next);
+                        chars.next();
+                    }
+                }
+
+                tokens.push(token);
+            }
+            _ => {
+                let mut token = String::new();
+
+                while let Some(&(_, ch)) = chars.peek() {
+                    if ch.is_whitespace() || "#\"'()!=<>&|".contains(ch) {
+                        break;
+                    }
+
+                    token.push(ch);
+                    chars.next();
+                }
+
+                tokens.push(token);
+            }
+        }
+    }
+
+    Ok(tokens)
+}
+
+/// Parses an expression with the usual precedence: `!` over comparisons over `&&` over `||`.
+fn parse_expr(tokens: &[String]) -> anyhow::Result<Expr> {
+    // Ignore trailing conditions, e.g. `default y if X`.
+    let end = tokens
+        .iter()
+        .position(|t| t == "if")
+        .unwrap_or(tokens.len());
+    let mut pos = 0;
+
+    let expr = parse_or(&tokens[..end], &mut pos)?;
+    if pos != end {
+        bail!("unexpected token {:?} in expression", tokens[pos]);
+    }
+
+    Ok(expr)
+}
+
+/// Parses the trailing `if` condition of an attribute, e.g. `select X if Y`.
+fn condition(tokens: &[String]) -> anyhow::Result<Option<Expr>> {
+    match tokens.iter().position(|t| t == "if") {
+        Some(i) => Ok(Some(parse_expr(&tokens[i + 1..])?)),
+        None => Ok(None),
+    }
+}
+
+fn parse_or(tokens: &[String], pos: &mut usize) -> anyhow::Result<Expr> {
+    let mut expr = parse_and(tokens, pos)?;
+
+    while tokens.get(*pos).is_some_and(|t| t == "||") {
+        *pos += 1;
+        expr = Expr::Or(Box::new(expr), Box::new(parse_and(tokens, pos)?));
+    }
+
+    Ok(expr)
+}
+
+fn parse_and(tokens: &[String], pos: &mut usize) -> anyhow::Result<Expr> {
+    let mut expr = parse_unary(tokens, pos)?;
+
+    while tokens.get(*pos).is_some_and(|t| t == "&&") {
+        *pos += 1;
+        expr = Expr::And(Box::new(expr), Box::new(parse_unary(tokens, pos)?));
+    }
+
+    Ok(expr)
+}
+
+fn parse_unary(tokens: &[String], pos: &mut usize) -> anyhow::Result<Expr> {
+    let token = tokens.get(*pos).context("unexpected end of expression")?;
+    *pos += 1;
+
+    match token.as_str() {
+        "!" => Ok(Expr::Not(Box::new(parse_unary(tokens, pos)?))),
+        "(" => {
+            let expr = parse_or(tokens, pos)?;
+
+            if tokens.get(*pos).map(String::as_str) != Some(")") {
+                bail!("missing ')' in expression");
+            }
+
+            *pos += 1;
+            Ok(expr)
+        }
+        _ => {
+            let op = tokens.get(*pos).map(String::as_str);
+
+            match op {
+                Some(op @ ("=" | "!=" | "<" | "<=" | ">" | ">=")) => {
+                    let rhs = tokens.get(*pos + 1).context("missing comparison operand")?;
+                    *pos += 2;
+
+                    Ok(Expr::Cmp(op.to_string(), token.clone(), rhs.clone()))
+                }
+                _ => Ok(Expr::Sym(token.clone())),
+            }
+        }
+    }
+}
+
+/// Expands `*` wildcards in whole path components, returning existing files only.
+fn expand_glob(path: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    if !path.to_string_lossy().contains('*') {
+        return Ok(if path.exists() {
+            vec![path.to_path_buf()]
+        } else {
+            Vec::new()
+        });
+    }
+
+    let mut paths = vec![PathBuf::new()];
+
+    for component in path.components() {
+        let component = component.as_os_str().to_string_lossy();
+
+        if component.contains('*') {
+            let (prefix, suffix) = component.split_once('*').unwrap();
+            let mut expanded = Vec::new();
+
+            for dir in &paths {
+                let Ok(entries) = fs::read_dir(dir) else {
+                    continue;
+                };
+
+                for entry in entries {
+                    let name = entry?.file_name().to_string_lossy().into_owned();
+                    if name.starts_with(prefix) && name.ends_with(suffix) {
+                        expanded.push(dir.join(name));
+                    }
+                }
+            }
+
+            expanded.sort();
+            paths = expanded;
+        } else {
+            for dir in &mut paths {
+                dir.push(component.as_ref());
+            }
+        }
+    }
+
+    paths.retain(|path| path.exists());
+    Ok(paths)
+}
+
+/// Reports whether an expression operand is a constant rather than a symbol name.
+fn is_constant(name: &str) -> bool {
+    matches!(name, "y" | "m" | "n")
+        || name.starts_with('"')
+        || name.starts_with("$(")
+        || parse_number(name).is_some()
+}
+
+fn parse_number(s: &str) -> Option<i64> {
+    match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => i64::from_str_radix(hex, 16).ok(),
+        None => s.parse().ok(),
+    }
+}
+
+fn tristate(value: u8) -> &'static str {
+    match value {
+        2 => "y",
+        1 => "m",
+        _ => "n",
+    }
+}
+
+/// Returns the `arch/` directory name for a kernel `ARCH`, e.g. `x86` for `x86_64`.
+pub fn srcarch(arch: &str) -> &str {
+    match arch {
+        "x86_64" | "i386" => "x86",
+        "sparc64" => "sparc",
+        "parisc64" => "parisc",
+        _ => arch,
+    }
+}
+
+/// Reads the architecture from the header of a `.config` file,
+/// e.g. `# Linux/arm64 6.15.4 Kernel Configuration`.
+fn config_arch(path: &Path) -> anyhow::Result<Option<String>> {
+    let text = fs::read_to_string(path)?;
+
+    Ok(text.lines().take(5).find_map(|line| {
+        let rest = line.strip_prefix("# Linux/")?;
+        Some(rest.split_whitespace().next()?.to_string())
+    }))
+}
+
+/// Returns `arch` if given, otherwise the architecture from the header of
+/// the `.config` file at `path`.
+pub fn config_arch_or(arch: Option<String>, path: &Path) -> anyhow::Result<String> {
+    match arch {
+        Some(arch) => Ok(arch),
+        None => match config_arch(path)? {
+            Some(arch) => Ok(arch),
+            None => bail!(
+                "can't determine the architecture of {}, use --arch",
+                path.display()
+            ),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture() -> Kconfig {
+        let srctree = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/kconfig");
+        Kconfig::parse(&srctree, "x86").unwrap()
+    }
+
+    fn config(options: &[(&str, &str)]) -> Config {
+        let mut config = Config::default();
+        for (symbol, value) in options {
+            config.set(symbol, value);
+        }
+
+        config
+    }
+
+    #[test]
+    fn parses_sources_menus_and_help() {
+        let kconfig = fixture();
+
+        // Sourced through $(SRCARCH) and a glob.
+        assert!(kconfig.defines("X86"));
+        assert!(kconfig.defines("DRIVER_C"));
+        // Only mentioned in help text.
+        assert!(!kconfig.defines("NOT_A_SYMBOL"));
+
+        let mut dependencies = kconfig
+            .dependencies(["DRIVER_C"])
+            .into_iter()
+            .collect::<Vec<_>>();
+        dependencies.sort();
+
+        // NET comes from the menu, NET_CORE from the if block.
+        assert_eq!(
+            dependencies,
+            ["DRIVER_C", "FEATURE", "MODULES", "NET", "NET_CORE"]
+        );
+    }
+
+    #[test]
+    fn explains_unmet_dependencies() {
+        let kconfig = fixture();
+        let config = config(&[("NET", "y"), ("NET_CORE", "y")]);
+
+        assert_eq!(
+            kconfig.explain(&config, "DRIVER_B", 0),
+            "CONFIG_DRIVER_B is not set\n  \
+             depends on X86 || MODULES [n] where X86=n, MODULES=n\n"
+        );
+
+        assert_eq!(
+            kconfig.explain(&config, "DRIVER_C", 1),
+            "CONFIG_DRIVER_C is not set\n  \
+             depends on FEATURE [n]\n    \
+             CONFIG_FEATURE is not set\n      \
+             dependencies are met\n      \
+             default y if NET && X86 [inactive]\n      \
+             default m [active]\n  \
+             depends on MODULES [n]\n    \
+             CONFIG_MODULES is not set\n      \
+             dependencies are met\n"
+        );
+    }
+
+    #[test]
+    fn explains_prompts_defaults_selects_and_choices() {
+        let kconfig = fixture();
+        let config = config(&[
+            ("X86", "y"),
+            ("NET", "y"),
+            ("NET_CORE", "y"),
+            ("DRIVER_A", "y"),
+        ]);
+
+        assert_eq!(
+            kconfig.explain(&config, "NET_CORE", 0),
+            "CONFIG_NET_CORE=y\n  \
+             dependencies are met\n  \
+             has no prompt, it is set by its defaults or selected\n  \
+             default y [active]\n"
+        );
+
+        assert_eq!(
+            kconfig.explain(&config, "HIDDEN_PROMPT", 0),
+            "CONFIG_HIDDEN_PROMPT is not set\n  \
+             dependencies are met\n  \
+             prompt is hidden by \"if MODULES\"\n"
+        );
+
+        assert_eq!(
+            kconfig.explain(&config, "SELECTED", 0),
+            "CONFIG_SELECTED is not set\n  \
+             dependencies are met\n  \
+             has no prompt, it can only be selected\n  \
+             selected by SELECTOR if NET [inactive]\n"
+        );
+
+        assert_eq!(
+            kconfig.explain(&config, "DRIVER_B", 0),
+            "CONFIG_DRIVER_B is not set\n  \
+             dependencies are met\n  \
+             is part of a choice, CONFIG_DRIVER_A is selected instead\n"
+        );
+
+        assert!(kconfig
+            .explain(&config, "NOT_A_SYMBOL", 0)
+            .contains("does not exist"));
+    }
+
+    #[test]
+    fn parses_expressions() {
+        let tokens = tokenize(r#"depends on !A && (B || C >= 0x10) if "x\"y""#).unwrap();
+        assert_eq!(
+            tokens,
+            [
+                "depends", "on", "!", "A", "&&", "(", "B", "||", "C", ">=", "0x10", ")", "if",
+                "\"x\"y\"",
+            ]
+        );
+
+        let expr = parse_expr(&tokens[2..]).unwrap();
+        assert_eq!(expr.to_string(), "!A && (B || C>=0x10)");
+        assert!(parse_expr(&tokens[2..6]).is_err());
+    }
+}
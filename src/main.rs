@@ -1,879 +1,376 @@
-use anyhow::bail;
-use clap::Parser;
-use std::env;
+mod audit;
+mod cache;
+mod config;
+mod diff;
+mod download;
+mod extract;
+mod fragment;
+mod kconfig;
+mod minimize;
+mod mirror;
+mod patch;
+mod releases;
+mod target;
+mod verify;
+mod version;
+
+use cache::Cache;
+use config::Config;
+use download::Downloader;
+use kconfig::Kconfig;
+use mirror::Mirrors;
+use releases::{Channel, Releases};
+use target::{Registry, Target};
+use verify::Verifier;
+use version::Version;
+
+use anyhow::{bail, Context};
+use clap::{Parser, Subcommand};
 use std::ffi::OsStr;
 use std::fs::{self, File};
 use std::io::{self, prelude::*};
-use std::path::Path;
-use std::process::{Command, Stdio};
-
-const LATEST: &str = "https://cdn.kernel.org/pub/linux/kernel/v6.x/linux-6.15.4.tar.xz";
-
-const CONFIG: &str = r#"
-CONFIG_ARCH_BCM2835=y
-CONFIG_HW_RANDOM_BCM2835=y
-CONFIG_DMA_BCM2835=y
-CONFIG_I2C_BCM2835=y
-CONFIG_SPI_BCM2835=y
-CONFIG_SPI_BCM2835AUX=y
-CONFIG_SERIAL_8250_BCM2835AUX=y
-CONFIG_BCM2835_WDT=y
-CONFIG_SND_BCM2835_SOC_I2S?y
-CONFIG_USB_USBNET=y
-CONFIG_USB_NET_SMSC95XX=y
-CONFIG_BCM2835_MBOX=y
-CONFIG_DYNAMIC_DEBUG=y
-CONFIG_BCMGENET=y
-CONFIG_BROADCOM_PHY=y
-CONFIG_ZONE_DMA=y
-CONFIG_ZONE_DMA32=y
-CONFIG_HOLES_IN_ZONE=y
-CONFIG_PCIE_BRCMSTB=y
-CONFIG_USB_NET_CDCETHER=y
-CONFIG_USB_VL600=y
-CONFIG_FB_SIMPLE=y
-CONFIG_DRM=y
-CONFIG_DRM_VC4=y
-CONFIG_DRM_VC4_HDMI_CEC=y
-
-CONFIG_SQUASHFS=y
-CONFIG_SQUASHFS_FILE_CACHE=y
-CONFIG_SQUASHFS_DECOMP_MULTI_PERCPU=y
-CONFIG_SQUASHFS_ZSTD=y
-CONFIG_RASPBERRYPI_FIRMWARE=y
-CONFIG_RASPBERRYPI_POWER=y
-CONFIG_TUN=y
-CONFIG_PPP=y
-CONFIG_PPPOE=y
-CONFIG_USB_NET_DRIVERS=y
-CONFIG_USB_RTL8150=y
-CONFIG_USB_RTL8152=y
-CONFIG_NETFILTER_NETLINK=y
-CONFIG_NETFILTER_NETLINK_QUEUE=y
-CONFIG_NET_IP_TUNNEL=y
-CONFIG_NET_SCH_INGRESS=y
-CONFIG_NET_SCHED=y
-CONFIG_INET_TUNNEL=y
-CONFIG_INET6_TUNNEL=y
-CONFIG_IPV6=y
-CONFIG_IPV6_SIT=y
-CONFIG_IPV6_TUNNEL=y
-CONFIG_IPV6_ADVANCED_ROUTER=y
-CONFIG_IPV6_MULTIPLE_TABLES=y
-CONFIG_IP_ADVANCED_ROUTER=y
-CONFIG_IP_MULTIPLE_TABLES=y
-CONFIG_VLAN_8021Q=y
-CONFIG_VLAN_8021Q_GVRP=y
-CONFIG_VLAN_8021Q_MVRP=y
-CONFIG_NF_CONNTRACK=y
-CONFIG_NF_LOG_COMMON=y
-CONFIG_NF_CONNTRACK_FTP=y
-CONFIG_NF_CONNTRACK_IRC=y
-CONFIG_NF_CONNTRACK_SIP=y
-CONFIG_NF_CONNTRACK_TFTP=y
-CONFIG_NF_CT_NETLINK=y
-CONFIG_NF_NAT=y
-CONFIG_NF_NAT_FTP=y
-CONFIG_NF_NAT_IRC=y
-CONFIG_NF_NAT_SIP=y
-CONFIG_NF_NAT_TFTP=y
-CONFIG_NF_NAT_MASQUERADE=y
-CONFIG_NETFILTER_XTABLES=y
-CONFIG_NETFILTER_XT_TARGET_LOG=y
-CONFIG_NETFILTER_XT_TARGET_MARK=y
-CONFIG_NETFILTER_XT_TARGET_TCPMSS=y
-CONFIG_NETFILTER_XT_NAT=y
-CONFIG_NETFILTER_XT_MATCH_ADDRTYPE=y
-CONFIG_NETFILTER_XT_MATCH_CONNTRACK=y
-CONFIG_NF_DEFRAG_IPV4=y
-CONFIG_NF_CONNTRACK_IPV4=y
-CONFIG_NF_LOG_IPV4=y
-CONFIG_NF_REJECT_IPV4=y
-CONFIG_NF_NAT_IPV4=y
-CONFIG_NF_NAT_MASQUERADE_IPV4=y
-CONFIG_IP_NF_IPTABLES=y
-CONFIG_IP_NF_FILTER=y
-CONFIG_IP_NF_TARGET_REJECT=y
-CONFIG_IP_NF_NAT=y
-CONFIG_IP_NF_TARGET_MASQUERADE=y
-CONFIG_IP_NF_MANGLE=y
-CONFIG_NF_DEFRAG_IPV6=y
-CONFIG_NF_CONNTRACK_IPV6=y
-CONFIG_NF_REJECT_IPV6=y
-CONFIG_NF_LOG_IPV6=y
-CONFIG_IP6_NF_IPTABLES=y
-CONFIG_IP6_NF_FILTER=y
-CONFIG_IP6_NF_TARGET_REJECT=y
-CONFIG_IP6_NF_MANGLE=y
-CONFIG_LIBCRC32C=y
-CONFIG_NF_TABLES=y
-CONFIG_NF_TABLES_INET=y
-CONFIG_NF_TABLES_IPV4=y
-CONFIG_NF_TABLES_IPV6=y
-CONFIG_NFT_CT=y
-CONFIG_NFT_LOG=y
-CONFIG_NFT_LIMIT=y
-CONFIG_NFT_MASQ=y
-CONFIG_NFT_NAT=y
-CONFIG_NFT_TUNNEL=y
-CONFIG_NFT_QUEUE=y
-CONFIG_NFT_REJECT=y
-CONFIG_NFT_REJECT_INET=y
-CONFIG_NFT_COMPAT=y
-CONFIG_NFT_SOCKET=y
-CONFIG_NFT_REJECT_NETDEV=y
-CONFIG_NFT_REJECT_IPV4=y
-CONFIG_NFT_REJECT_IPV6=y
-CONFIG_NET_UDP_TUNNEL=y
-CONFIG_WIREGUARD=y
-
-# TODO: trim the settings below to the minimum set that works (taken from debian)
-##
-## file: arch/arm64/Kconfig
-##
-CONFIG_PCI=y
-CONFIG_ARM64_ERRATUM_834220=y
-#. Until we decide how/whether to handle this in userland as well
-# CONFIG_ARM64_ERRATUM_843419 is not set
-## choice: Virtual address space size
-CONFIG_ARM64_VA_BITS_48=y
-## end choice
-CONFIG_SCHED_MC=y
-CONFIG_SECCOMP=y
-CONFIG_XEN=y
-CONFIG_RANDOMIZE_BASE=y
-CONFIG_RANDOMIZE_MODULE_REGION_FULL=y
-CONFIG_ARM64_ACPI_PARKING_PROTOCOL=y
-CONFIG_EFI_SECURE_BOOT_SECURELEVEL=y
-CONFIG_COMPAT=y
-##
-## file: arch/arm64/crypto/Kconfig
-##
-CONFIG_ARM64_CRYPTO=y
-CONFIG_CRYPTO_SHA1_ARM64_CE=y
-CONFIG_CRYPTO_SHA2_ARM64_CE=y
-CONFIG_CRYPTO_GHASH_ARM64_CE=y
-CONFIG_CRYPTO_AES_ARM64_CE=y
-CONFIG_CRYPTO_AES_ARM64_CE_CCM=y
-CONFIG_CRYPTO_AES_ARM64_CE_BLK=y
-# CONFIG_CRYPTO_AES_ARM64_NEON_BLK is not set
-CONFIG_CRYPTO_CRC32_ARM64=y
-##
-## file: arch/arm64/kvm/Kconfig
-##
-CONFIG_VIRTUALIZATION=y
-CONFIG_KVM=y
-##
-## file: arch/arm64/Kconfig.platforms
-##
-CONFIG_ARCH_BCM2835=y
-CONFIG_ARCH_HISI=y
-CONFIG_ARCH_MESON=y
-CONFIG_ARCH_MVEBU=y
-CONFIG_ARCH_QCOM=y
-CONFIG_ARCH_SEATTLE=y
-CONFIG_ARCH_TEGRA=y
-CONFIG_ARCH_THUNDER=y
-CONFIG_ARCH_VEXPRESS=y
-CONFIG_ARCH_XGENE=y
-##
-## file: drivers/acpi/Kconfig
-##
-CONFIG_ACPI=y
-##
-## file: drivers/ata/Kconfig
-##
-CONFIG_SATA_AHCI_PLATFORM=y
-CONFIG_AHCI_MVEBU=y
-CONFIG_AHCI_TEGRA=y
-CONFIG_AHCI_XGENE=y
-CONFIG_SATA_AHCI_SEATTLE=y
-##
-## file: drivers/bluetooth/Kconfig
-##
-CONFIG_BT_HCIUART=y
-CONFIG_BT_QCOMSMD=y
-##
-## file: drivers/bus/Kconfig
-##
-CONFIG_QCOM_EBI2=y
-CONFIG_TEGRA_ACONNECT=y
-##
-## file: drivers/char/hw_random/Kconfig
-##
-CONFIG_HW_RANDOM_BCM2835=y
-CONFIG_HW_RANDOM_HISI=y
-CONFIG_HW_RANDOM_MSM=y
-CONFIG_HW_RANDOM_XGENE=y
-CONFIG_HW_RANDOM_MESON=y
-CONFIG_HW_RANDOM_CAVIUM=y
-##
-## file: drivers/char/ipmi/Kconfig
-##
-CONFIG_IPMI_HANDLER=y
-CONFIG_IPMI_DEVICE_INTERFACE=y
-CONFIG_IPMI_SSIF=y
-##
-## file: drivers/clk/Kconfig
-##
-CONFIG_COMMON_CLK_XGENE=y
-##
-## file: drivers/clk/hisilicon/Kconfig
-##
-CONFIG_STUB_CLK_HI6220=y
-##
-## file: drivers/clk/qcom/Kconfig
-##
-CONFIG_COMMON_CLK_QCOM=y
-CONFIG_MSM_GCC_8916=y
-CONFIG_MSM_GCC_8996=y
-CONFIG_MSM_MMCC_8996=y
-##
-## file: drivers/cpufreq/Kconfig
-##
-CONFIG_CPUFREQ_DT=y
-##
-## file: drivers/cpuidle/Kconfig.arm
-##
-CONFIG_ARM_CPUIDLE=y
-##
-## file: drivers/crypto/Kconfig
-##
-CONFIG_CRYPTO_DEV_QCE=y
-##
-## file: drivers/dma/Kconfig
-##
-CONFIG_DMADEVICES=y
-CONFIG_DMA_BCM2835=y
-CONFIG_K3_DMA=y
-CONFIG_MV_XOR=y
-CONFIG_MV_XOR_V2=y
-CONFIG_TEGRA20_APB_DMA=y
-CONFIG_TEGRA210_ADMA=y
-CONFIG_XGENE_DMA=y
-##
-## file: drivers/dma/qcom/Kconfig
-##
-CONFIG_QCOM_BAM_DMA=y
-CONFIG_QCOM_HIDMA_MGMT=y
-CONFIG_QCOM_HIDMA=y
-##
-## file: drivers/edac/Kconfig
-##
-CONFIG_EDAC=y
-CONFIG_EDAC_MM_EDAC=y
-CONFIG_EDAC_XGENE=y
-##
-## file: drivers/extcon/Kconfig
-##
-CONFIG_EXTCON=y
-CONFIG_EXTCON_QCOM_SPMI_MISC=y
-CONFIG_EXTCON_USB_GPIO=y
-##
-## file: drivers/firmware/Kconfig
-##
-CONFIG_RASPBERRYPI_FIRMWARE=y
-##
-## file: drivers/gpio/Kconfig
-##
-CONFIG_GPIOLIB=y
-CONFIG_GPIO_PL061=y
-CONFIG_GPIO_XGENE=y
-CONFIG_GPIO_XGENE_SB=y
-CONFIG_GPIO_PCA953X=y
-CONFIG_GPIO_PCA953X_IRQ=y
-CONFIG_GPIO_MAX77620=y
-##
-## file: drivers/gpu/drm/Kconfig
-##
-CONFIG_DRM=y
-##
-## file: drivers/gpu/drm/bridge/adv7511/Kconfig
-##
-CONFIG_DRM_I2C_ADV7511=y
-##
-## file: drivers/gpu/drm/hisilicon/kirin/Kconfig
-##
-CONFIG_DRM_HISI_KIRIN=y
-##
-## file: drivers/gpu/drm/meson/Kconfig
-##
-CONFIG_DRM_MESON=y
-##
-## file: drivers/gpu/drm/msm/Kconfig
-##
-CONFIG_DRM_MSM=y
-CONFIG_DRM_MSM_DSI=y
-CONFIG_DRM_MSM_DSI_PLL=y
-CONFIG_DRM_MSM_DSI_28NM_PHY=y
-CONFIG_DRM_MSM_DSI_20NM_PHY=y
-##
-## file: drivers/gpu/drm/panel/Kconfig
-##
-CONFIG_DRM_PANEL_SIMPLE=y
-##
-## file: drivers/gpu/drm/tegra/Kconfig
-##
-CONFIG_DRM_TEGRA=y
-CONFIG_DRM_TEGRA_STAGING=y
-##
-## file: drivers/gpu/drm/vc4/Kconfig
-##
-CONFIG_DRM_VC4=y
-##
-## file: drivers/gpu/host1x/Kconfig
-##
-CONFIG_TEGRA_HOST1X=y
-##
-## file: drivers/hwmon/Kconfig
-##
-CONFIG_SENSORS_XGENE=y
-##
-## file: drivers/hwspinlock/Kconfig
-##
-CONFIG_HWSPINLOCK_QCOM=y
-##
-## file: drivers/iio/adc/Kconfig
-##
-CONFIG_QCOM_SPMI_IADC=y
-CONFIG_QCOM_SPMI_VADC=y
-##
-## file: drivers/input/keyboard/Kconfig
-##
-CONFIG_KEYBOARD_GPIO=y
-CONFIG_KEYBOARD_TEGRA=y
-##
-## file: drivers/input/misc/Kconfig
-##
-CONFIG_INPUT_MISC=y
-CONFIG_INPUT_PM8941_PWRKEY=y
-CONFIG_INPUT_UINPUT=y
-CONFIG_INPUT_HISI_POWERKEY=y
-##
-## file: drivers/iommu/Kconfig
-##
-CONFIG_TEGRA_IOMMU_SMMU=y
-CONFIG_ARM_SMMU=y
-CONFIG_ARM_SMMU_V3=y
-##
-## file: drivers/leds/Kconfig
-##
-CONFIG_LEDS_GPIO=y
-##
-## file: drivers/mailbox/Kconfig
-##
-CONFIG_MAILBOX=y
-CONFIG_BCM2835_MBOX=y
-CONFIG_HI6220_MBOX=y
-CONFIG_XGENE_SLIMPRO_MBOX=y
-##
-## file: drivers/memory/tegra/Kconfig
-##
-CONFIG_TEGRA_MC=y
-##
-## file: drivers/mfd/Kconfig
-##
-CONFIG_MFD_CROS_EC=y
-CONFIG_MFD_CROS_EC_I2C=y
-CONFIG_MFD_CROS_EC_SPI=y
-CONFIG_MFD_HI655X_PMIC=y
-CONFIG_MFD_MAX77620=y
-CONFIG_MFD_QCOM_RPM=y
-CONFIG_MFD_SPMI_PMIC=y
-##
-## file: drivers/misc/Kconfig
-##
-CONFIG_QCOM_COINCELL=y
-##
-## file: drivers/misc/ti-st/Kconfig
-##
-CONFIG_TI_ST=y
-##
-## file: drivers/mmc/Kconfig
-##
-CONFIG_MMC=y
-##
-## file: drivers/mmc/host/Kconfig
-##
-CONFIG_MMC_ARMMMCI=y
-CONFIG_MMC_QCOM_DML=y
-CONFIG_MMC_SDHCI_PLTFM=y
-CONFIG_MMC_SDHCI_TEGRA=y
-CONFIG_MMC_SDHCI_IPROC=y
-CONFIG_MMC_MESON_GX=y
-CONFIG_MMC_SDHCI_MSM=y
-CONFIG_MMC_SPI=y
-CONFIG_MMC_DW=y
-CONFIG_MMC_DW_K3=y
-##
-## file: drivers/mtd/spi-nor/Kconfig
-##
-CONFIG_SPI_HISI_SFC=y
-##
-## file: drivers/net/ethernet/Kconfig
-##
-CONFIG_FEALNX=y
-##
-## file: drivers/net/ethernet/3com/Kconfig
-##
-CONFIG_NET_VENDOR_3COM=y
-CONFIG_VORTEX=y
-CONFIG_TYPHOON=y
-##
-## file: drivers/net/ethernet/8390/Kconfig
-##
-CONFIG_NET_VENDOR_8390=y
-CONFIG_NE2K_PCI=y
-##
-## file: drivers/net/ethernet/adaptec/Kconfig
-##
-CONFIG_NET_VENDOR_ADAPTEC=y
-CONFIG_ADAPTEC_STARFIRE=y
-##
-## file: drivers/net/ethernet/amd/Kconfig
-##
-CONFIG_AMD_XGBE=y
-##
-## file: drivers/net/ethernet/apm/xgene/Kconfig
-##
-CONFIG_NET_XGENE=y
-##
-## file: drivers/net/ethernet/cavium/Kconfig
-##
-CONFIG_NET_VENDOR_CAVIUM=y
-CONFIG_THUNDER_NIC_PF=y
-CONFIG_THUNDER_NIC_VF=y
-CONFIG_THUNDER_NIC_BGX=y
-CONFIG_THUNDER_NIC_RGX=y
-##
-## file: drivers/net/ethernet/dec/tulip/Kconfig
-##
-CONFIG_NET_TULIP=y
-CONFIG_DE2104X=y
-CONFIG_TULIP=y
-# CONFIG_TULIP_MWI is not set
-# CONFIG_TULIP_MMIO is not set
-CONFIG_WINBOND_840=y
-CONFIG_DM9102=y
-##
-## file: drivers/net/ethernet/dlink/Kconfig
-##
-CONFIG_NET_VENDOR_DLINK=y
-CONFIG_SUNDANCE=y
-# CONFIG_SUNDANCE_MMIO is not set
-##
-## file: drivers/net/ethernet/hisilicon/Kconfig
-##
-CONFIG_NET_VENDOR_HISILICON=y
-CONFIG_HIX5HD2_GMAC=y
-CONFIG_HISI_FEMAC=y
-CONFIG_HIP04_ETH=y
-CONFIG_HNS=y
-CONFIG_HNS_DSAF=y
-CONFIG_HNS_ENET=y
-##
-## file: drivers/net/ethernet/intel/Kconfig
-##
-CONFIG_NET_VENDOR_INTEL=y
-CONFIG_E100=y
-##
-## file: drivers/net/ethernet/natsemi/Kconfig
-##
-CONFIG_NET_VENDOR_NATSEMI=y
-CONFIG_NATSEMI=y
-##
-## file: drivers/net/ethernet/realtek/Kconfig
-##
-CONFIG_8139CP=y
-CONFIG_8139TOO=y
-##
-## file: drivers/net/ethernet/smsc/Kconfig
-##
-CONFIG_NET_VENDOR_SMSC=y
-CONFIG_SMC91X=y
-CONFIG_EPIC100=y
-CONFIG_SMSC911X=y
-##
-## file: drivers/net/ethernet/stmicro/stmmac/Kconfig
-##
-CONFIG_STMMAC_ETH=y
-CONFIG_STMMAC_PLATFORM=y
-CONFIG_DWMAC_GENERIC=y
-CONFIG_DWMAC_IPQ806X=y
-CONFIG_DWMAC_MESON=y
-##
-## file: drivers/net/fddi/Kconfig
-##
-CONFIG_FDDI=y
-CONFIG_SKFP=y
-##
-## file: drivers/net/phy/Kconfig
-##
-CONFIG_MDIO_HISI_FEMAC=y
-CONFIG_MDIO_THUNDER=y
-CONFIG_MDIO_XGENE=y
-CONFIG_MESON_GXL_PHY=y
-##
-## file: drivers/net/wireless/ath/wcn36xx/Kconfig
-##
-CONFIG_WCN36XX=y
-##
-## file: drivers/net/wireless/ti/Kconfig
-##
-CONFIG_WLAN_VENDOR_TI=y
-CONFIG_WILINK_PLATFORM_DATA=y
-##
-## file: drivers/net/wireless/ti/wl1251/Kconfig
-##
-CONFIG_WL1251=y
-CONFIG_WL1251_SPI=y
-CONFIG_WL1251_SDIO=y
-##
-## file: drivers/net/wireless/ti/wl12xx/Kconfig
-##
-CONFIG_WL12XX=y
-##
-## file: drivers/net/wireless/ti/wl18xx/Kconfig
-##
-CONFIG_WL18XX=y
-##
-## file: drivers/net/wireless/ti/wlcore/Kconfig
-##
-CONFIG_WLCORE=y
-CONFIG_WLCORE_SPI=y
-CONFIG_WLCORE_SDIO=y
-##
-## file: drivers/nvmem/Kconfig
-##
-CONFIG_QCOM_QFPROM=y
-##
-## file: drivers/pci/host/Kconfig
-##
-CONFIG_PCI_AARDVARK=y
-CONFIG_PCI_HOST_GENERIC=y
-CONFIG_PCI_XGENE=y
-CONFIG_PCI_HISI=y
-CONFIG_PCIE_QCOM=y
-CONFIG_PCI_HOST_THUNDER_PEM=y
-CONFIG_PCI_HOST_THUNDER_ECAM=y
-CONFIG_PCIE_ARMADA_8K=y
-##
-## file: drivers/phy/Kconfig
-##
-CONFIG_PHY_HI6220_USB=y
-CONFIG_PHY_QCOM_APQ8064_SATA=y
-CONFIG_PHY_QCOM_IPQ806X_SATA=y
-CONFIG_PHY_XGENE=y
-CONFIG_PHY_QCOM_UFS=y
-CONFIG_PHY_MESON8B_USB2=y
-##
-## file: drivers/phy/tegra/Kconfig
-##
-CONFIG_PHY_TEGRA_XUSB=y
-##
-## file: drivers/pinctrl/Kconfig
-##
-CONFIG_PINCTRL_AMD=y
-CONFIG_PINCTRL_SINGLE=y
-CONFIG_PINCTRL_MAX77620=y
-##
-## file: drivers/pinctrl/qcom/Kconfig
-##
-CONFIG_PINCTRL_MSM8916=y
-CONFIG_PINCTRL_MSM8996=y
-CONFIG_PINCTRL_QCOM_SPMI_PMIC=y
-CONFIG_PINCTRL_QCOM_SSBI_PMIC=y
-##
-## file: drivers/platform/chrome/Kconfig
-##
-CONFIG_CHROME_PLATFORMS=y
-##
-## file: drivers/power/reset/Kconfig
-##
-CONFIG_POWER_RESET_HISI=y
-CONFIG_POWER_RESET_MSM=y
-CONFIG_POWER_RESET_VEXPRESS=y
-CONFIG_POWER_RESET_XGENE=y
-CONFIG_POWER_RESET_SYSCON=y
-CONFIG_POWER_RESET_SYSCON_POWEROFF=y
-##
-## file: drivers/power/supply/Kconfig
-##
-CONFIG_BATTERY_BQ27XXX=y
-CONFIG_CHARGER_QCOM_SMBB=y
-##
-## file: drivers/pwm/Kconfig
-##
-CONFIG_PWM=y
-CONFIG_PWM_BCM2835=y
-CONFIG_PWM_MESON=y
-CONFIG_PWM_TEGRA=y
-##
-## file: drivers/regulator/Kconfig
-##
-CONFIG_REGULATOR=y
-CONFIG_REGULATOR_FIXED_VOLTAGE=y
-CONFIG_REGULATOR_HI655X=y
-CONFIG_REGULATOR_MAX77620=y
-CONFIG_REGULATOR_PWM=y
-CONFIG_REGULATOR_QCOM_RPM=y
-CONFIG_REGULATOR_QCOM_SMD_RPM=y
-CONFIG_REGULATOR_QCOM_SPMI=y
-##
-## file: drivers/remoteproc/Kconfig
-##
-CONFIG_QCOM_Q6V5_PIL=y
-#. We want to enable this but it currently results in a dependency loop!
-# CONFIG_QCOM_WCNSS_PIL is not set
-##
-## file: drivers/reset/Kconfig
-##
-CONFIG_RESET_CONTROLLER=y
-CONFIG_RESET_MESON=y
-# No longer in the arm64 defconfig due to commit 8ae030c34dce4f5764e945b325e8dc4d2adef044:
-CONFIG_RESET_RASPBERRYPI=y
-##
-## file: drivers/reset/hisilicon/Kconfig
-##
-CONFIG_COMMON_RESET_HI6220=y
-##
-## file: drivers/rtc/Kconfig
-##
-CONFIG_RTC_DRV_DS1307=y
-CONFIG_RTC_DRV_MAX77686=y
-CONFIG_RTC_DRV_EFI=y
-CONFIG_RTC_DRV_PL031=y
-CONFIG_RTC_DRV_PM8XXX=y
-CONFIG_RTC_DRV_TEGRA=y
-CONFIG_RTC_DRV_XGENE=y
-##
-## file: drivers/scsi/Kconfig
-##
-CONFIG_SCSI_DMX3191D=y
-##
-## file: drivers/scsi/hisi_sas/Kconfig
-##
-CONFIG_SCSI_HISI_SAS=y
-##
-## file: drivers/soc/bcm/Kconfig
-##
-CONFIG_RASPBERRYPI_POWER=y
-##
-## file: drivers/soc/qcom/Kconfig
-##
-CONFIG_QCOM_GSBI=y
-CONFIG_QCOM_SMEM=y
-CONFIG_QCOM_SMD=y
-CONFIG_QCOM_SMD_RPM=y
-CONFIG_QCOM_SMP2P=y
-CONFIG_QCOM_SMSM=y
-CONFIG_QCOM_WCNSS_CTRL=y
-##
-## file: drivers/soc/tegra/Kconfig
-##
-CONFIG_ARCH_TEGRA_132_SOC=y
-CONFIG_ARCH_TEGRA_210_SOC=y
-##
-## file: drivers/spi/Kconfig
-##
-CONFIG_SPI_BCM2835=y
-CONFIG_SPI_BCM2835AUX=y
-CONFIG_SPI_MESON_SPIFC=y
-CONFIG_SPI_QUP=y
-CONFIG_SPI_TEGRA114=y
-CONFIG_SPI_TEGRA20_SFLASH=y
-CONFIG_SPI_TEGRA20_SLINK=y
-CONFIG_SPI_THUNDERX=y
-##
-## file: drivers/spmi/Kconfig
-##
-CONFIG_SPMI=y
-CONFIG_SPMI_MSM_PMIC_ARB=y
-##
-## file: drivers/thermal/Kconfig
-##
-CONFIG_THERMAL=y
-CONFIG_CPU_THERMAL=y
-CONFIG_HISI_THERMAL=y
-CONFIG_QCOM_SPMI_TEMP_ALARM=y
-##
-## file: drivers/thermal/qcom/Kconfig
-##
-CONFIG_QCOM_TSENS=y
-##
-## file: drivers/thermal/tegra/Kconfig
-##
-CONFIG_TEGRA_SOCTHERM=y
-##
-## file: drivers/tty/serial/Kconfig
-##
-CONFIG_SERIAL_AMBA_PL010=y
-CONFIG_SERIAL_AMBA_PL010_CONSOLE=y
-CONFIG_SERIAL_AMBA_PL011=y
-CONFIG_SERIAL_AMBA_PL011_CONSOLE=y
-CONFIG_SERIAL_MESON=y
-CONFIG_SERIAL_MESON_CONSOLE=y
-CONFIG_SERIAL_TEGRA=y
-CONFIG_SERIAL_MSM=y
-CONFIG_SERIAL_MSM_CONSOLE=y
-CONFIG_SERIAL_MVEBU_UART=y
-CONFIG_SERIAL_MVEBU_CONSOLE=y
-##
-## file: drivers/tty/serial/8250/Kconfig
-##
-CONFIG_SERIAL_8250=y
-CONFIG_SERIAL_8250_DEPRECATED_OPTIONS=y
-CONFIG_SERIAL_8250_CONSOLE=y
-CONFIG_SERIAL_8250_DMA=y
-CONFIG_SERIAL_8250_NR_UARTS=1
-CONFIG_SERIAL_8250_RUNTIME_UARTS=1
-CONFIG_SERIAL_8250_EXTENDED=y
-CONFIG_SERIAL_8250_SHARE_IRQ=y
-CONFIG_SERIAL_8250_BCM2835AUX=y
-CONFIG_SERIAL_8250_DW=y
-# CONFIG_SERIAL_8250_EM is not set
-CONFIG_SERIAL_OF_PLATFORM=y
-##
-## file: drivers/usb/chipidea/Kconfig
-##
-CONFIG_USB_CHIPIDEA=y
-CONFIG_USB_CHIPIDEA_UDC=y
-CONFIG_USB_CHIPIDEA_HOST=y
-##
-## file: drivers/usb/dwc2/Kconfig
-##
-CONFIG_USB_DWC2=y
-## choice: DWC2 Mode Selection
-CONFIG_USB_DWC2_DUAL_ROLE=y
-## end choice
-##
-## file: drivers/usb/dwc3/Kconfig
-##
-CONFIG_USB_DWC3=y
-## choice: DWC3 Mode Selection
-CONFIG_USB_DWC3_DUAL_ROLE=y
-## end choice
-##
-## file: drivers/usb/gadget/Kconfig
-##
-CONFIG_USB_GADGET=y
-##
-## file: drivers/usb/host/Kconfig
-##
-#. xhci-platform apparently does not build as module, so xhci_hcd can't be either
-CONFIG_USB_XHCI_HCD=y
-CONFIG_USB_XHCI_PCI=y
-CONFIG_USB_XHCI_PCI_RENESAS=y
-CONFIG_USB_XHCI_PLATFORM=y
-CONFIG_USB_XHCI_TEGRA=y
-CONFIG_USB_EHCI_HCD=y
-CONFIG_USB_EHCI_MSM=y
-CONFIG_USB_EHCI_TEGRA=y
-CONFIG_USB_EHCI_HCD_PLATFORM=y
-CONFIG_USB_OHCI_HCD=y
-CONFIG_USB_OHCI_HCD_PLATFORM=y
-##
-## file: drivers/usb/misc/Kconfig
-##
-CONFIG_USB_HSIC_USB3503=y
-##
-## file: drivers/usb/phy/Kconfig
-##
-CONFIG_USB_MSM_OTG=y
-CONFIG_USB_QCOM_8X16_PHY=y
-##
-## file: drivers/video/backlight/Kconfig
-##
-CONFIG_BACKLIGHT_GENERIC=y
-CONFIG_BACKLIGHT_LP855X=y
-##
-## file: drivers/video/fbdev/Kconfig
-##
-CONFIG_FB_EFI=y
-##
-## file: drivers/virtio/Kconfig
-##
-CONFIG_VIRTIO_MMIO=y
-##
-## file: drivers/watchdog/Kconfig
-##
-CONFIG_TEGRA_WATCHDOG=y
-CONFIG_QCOM_WDT=y
-CONFIG_MESON_GXBB_WATCHDOG=y
-CONFIG_MESON_WATCHDOG=y
-CONFIG_BCM2835_WDT=y
-##
-## file: fs/pstore/Kconfig
-##
-CONFIG_PSTORE=y
-##
-## file: net/bluetooth/Kconfig
-##
-CONFIG_BT_LEDS=y
-##
-## file: sound/pci/hda/Kconfig
-##
-CONFIG_SND_HDA_TEGRA=y
-##
-## file: sound/soc/Kconfig
-##
-CONFIG_SND_SOC=y
-##
-## file: sound/soc/bcm/Kconfig
-##
-CONFIG_SND_BCM2835_SOC_I2S=y
-##
-## file: sound/soc/qcom/Kconfig
-##
-CONFIG_SND_SOC_QCOM=y
-CONFIG_SND_SOC_APQ8016_SBC=y
-##
-## file: sound/soc/tegra/Kconfig
-##
-CONFIG_SND_SOC_TEGRA=y
-CONFIG_SND_SOC_TEGRA_RT5640=y
-CONFIG_SND_SOC_TEGRA_WM8753=y
-CONFIG_SND_SOC_TEGRA_WM8903=y
-CONFIG_SND_SOC_TEGRA_TRIMSLICE=y
-CONFIG_SND_SOC_TEGRA_ALC5632=y
-CONFIG_SND_SOC_TEGRA_MAX98090=y
-CONFIG_SND_SOC_TEGRA_RT5677=y
-"#;
+use std::path::{Path, PathBuf};
+use std::process::{self, Command, Stdio};
+use xz2::read::XzDecoder;
+
+const LATEST: &str = "6.18.1";
+
+/// File marking a kernel tree unpacked by us, which may be deleted.
+const UNPACKED_MARKER: &str = ".rustkrazy-unpacked";
 
 #[derive(Debug, Parser)]
 #[command(author = "The Rustkrazy Authors", version = "v0.1.0", about = "Build the rustkrazy kernel", long_about = None)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 struct Args {
-    /// Output architecture.
-    #[arg(short = 'a', long = "architecture")]
-    arch: String,
+    #[command(subcommand)]
+    command: Option<Cmd>,
+    /// Target to build for, see the targets command.
+    #[arg(short = 'a', long = "architecture", required = true)]
+    arch: Option<String>,
+    /// JSON file with additional targets or replacements for built-in ones.
+    #[arg(long = "targets-file")]
+    targets_files: Vec<PathBuf>,
+    /// Kernel version to build, e.g. 6.6.58 or 6.12.
+    #[arg(short = 'k', long = "kernel", default_value = LATEST)]
+    kernel: Version,
+    /// Build the current release of a kernel.org channel instead of a fixed version.
+    #[arg(long = "channel", conflicts_with = "kernel")]
+    channel: Option<Channel>,
+    /// URL or path of the kernel.org release list used to resolve channels.
+    #[arg(long = "releases-url", default_value = releases::RELEASES_URL)]
+    releases_url: String,
+    /// OpenPGP keyring holding the kernel.org release keys.
+    #[arg(long = "keyring", default_value = "keys/kernel.org.gpg")]
+    keyring: PathBuf,
+    /// Fingerprint of a key to trust instead of the kernel.org release keys.
+    #[arg(long = "trusted-key")]
+    trusted_keys: Vec<String>,
+    /// URL or path of the signed sha256sums.asc checksum list.
+    #[arg(long = "checksums")]
+    checksums: Option<String>,
+    /// URL or path of the detached .tar.sign signature.
+    #[arg(long = "signature")]
+    signature: Option<String>,
+    /// Directory for cached downloads [default: $XDG_CACHE_HOME/rustkrazy-kernel].
+    #[arg(long = "cache-dir")]
+    cache_dir: Option<PathBuf>,
+    /// Build from an existing kernel source tree instead of downloading one.
+    #[arg(long = "source-dir", conflicts_with_all = ["tarball", "extra_patches"])]
+    source_dir: Option<PathBuf>,
+    /// Base URL of a kernel.org mirror, tried in the order given.
+    /// Falls back to $RUSTKRAZY_KERNEL_MIRRORS, then the mirror file, then cdn.kernel.org.
+    #[arg(long = "mirror")]
+    mirrors: Vec<String>,
+    /// File listing one mirror per line [default: $XDG_CONFIG_HOME/rustkrazy-kernel/mirrors].
+    #[arg(long = "mirror-file")]
+    mirror_file: Option<PathBuf>,
+    /// Directory holding the patch series applied to every build,
+    /// with per-architecture and per-target series in subdirectories.
+    #[arg(long = "patches", default_value = "patches")]
+    patches: PathBuf,
+    /// Additional patch directory applied after the built-in series.
+    #[arg(long = "extra-patches")]
+    extra_patches: Vec<PathBuf>,
+    /// Directory holding the kernel config fragments.
+    #[arg(long = "config-dir", default_value = "config")]
+    config_dir: PathBuf,
+    /// Feature profile to enable, e.g. router, vpn, virt-host or minimal
+    /// [default: the profiles of the target].
+    #[arg(long = "profile")]
+    profiles: Vec<String>,
+    /// Additional config fragment merged after the built-in ones.
+    #[arg(long = "fragment")]
+    fragments: Vec<PathBuf>,
+    /// Set a kernel option after merging the fragments, e.g. CONFIG_FOO=y or CONFIG_BAR="string".
+    #[arg(long = "set", value_name = "CONFIG_X=VALUE", value_parser = fragment::parse_set)]
+    set: Vec<(String, String)>,
+    /// Disable a kernel option after merging the fragments.
+    #[arg(long = "unset", value_name = "CONFIG_X", value_parser = fragment::parse_unset)]
+    unset: Vec<String>,
+    /// Fail the build if any requested config option didn't survive `make olddefconfig`.
+    #[arg(long = "strict")]
+    strict: bool,
+    /// Always download the full tarball instead of patching the base release of the series.
+    #[arg(long = "full-tarball")]
+    full_tarball: bool,
+    /// Number of retries per mirror before moving on to the next one.
+    #[arg(long = "retries", default_value_t = 3)]
+    retries: u32,
+    /// Build from a local kernel source tarball instead of downloading one.
+    #[arg(long = "tarball")]
+    tarball: Option<PathBuf>,
+}
+
+#[derive(Debug, Subcommand)]
+enum Cmd {
+    /// Check kernel.org for a newer release of the kernel series,
+    /// exiting with status 1 if an update is due.
+    CheckUpdate {
+        /// Kernel version to check.
+        #[arg(short = 'k', long = "kernel", default_value = LATEST)]
+        kernel: Version,
+        /// File containing the kernel version to check, e.g. a version-<arch> build output.
+        #[arg(long = "lockfile", conflicts_with = "kernel")]
+        lockfile: Option<PathBuf>,
+        /// URL or path of the kernel.org release list.
+        #[arg(long = "releases-url", default_value = releases::RELEASES_URL)]
+        releases_url: String,
+    },
+    /// Check config fragments for malformed lines and duplicate or contradictory assignments.
+    Lint {
+        /// Fragment files to check [default: all fragments in the config directory].
+        files: Vec<PathBuf>,
+        /// Directory holding the kernel config fragments.
+        #[arg(long = "config-dir", default_value = "config")]
+        config_dir: PathBuf,
+        /// Fail on warnings as well as errors.
+        #[arg(long = "strict")]
+        strict: bool,
+    },
+    /// List the targets that can be built.
+    Targets {
+        /// JSON file with additional targets or replacements for built-in ones.
+        #[arg(long = "targets-file")]
+        targets_files: Vec<PathBuf>,
+    },
+    /// List the feature profiles and the options each one sets.
+    Profiles {
+        /// Directory holding the kernel config fragments.
+        #[arg(long = "config-dir", default_value = "config")]
+        config_dir: PathBuf,
+    },
+    /// Reduce an architecture config fragment to the options needed by the hardware
+    /// listed in manifest files.
+    Minimize {
+        /// Manifest files listing required config symbols, modules, drivers
+        /// or device tree compatibles.
+        #[arg(required = true)]
+        manifests: Vec<PathBuf>,
+        /// Unpacked kernel source tree, built with the current config to report size savings.
+        #[arg(long = "source-dir")]
+        source_dir: PathBuf,
+        /// Kernel architecture (ARCH).
+        #[arg(long = "arch", default_value = "arm64")]
+        arch: String,
+        /// Fragment to minimize [default: <config-dir>/arch/<arch>.config].
+        #[arg(long = "fragment")]
+        fragment: Option<PathBuf>,
+        /// Directory holding the kernel config fragments.
+        #[arg(long = "config-dir", default_value = "config")]
+        config_dir: PathBuf,
+        /// Where to write the minimal fragment.
+        #[arg(short = 'o', long = "output", default_value = "minimal.config")]
+        output: PathBuf,
+    },
+    /// Check a final kernel config against hardening recommendations,
+    /// exiting with status 1 if any check fails.
+    Audit {
+        /// Kernel config to check, e.g. config-<target> of a build.
+        config: PathBuf,
+        /// Kernel architecture (ARCH) [default: read from the config header].
+        #[arg(long = "arch")]
+        arch: Option<String>,
+    },
+    /// Compare the effective configuration of two builds, grouped by menu.
+    /// Each side is a .config file, a build output directory or kernel tree,
+    /// or a kernel version to configure with the same fragments.
+    ConfigDiff {
+        /// Old config file, directory or kernel version.
+        old: String,
+        /// New config file, directory or kernel version.
+        new: String,
+        /// Target whose config-<target> to compare in output directories
+        /// and to configure kernel versions for.
+        #[arg(short = 'a', long = "architecture")]
+        target: Option<String>,
+        /// Build options used to configure kernel versions, e.g. -- --profile vpn.
+        #[arg(last = true)]
+        build_args: Vec<String>,
+    },
+    /// Explain why kernel options are not enabled in a configured kernel tree
+    /// by following their unmet dependencies.
+    Explain {
+        /// Options to explain, with or without the CONFIG_ prefix.
+        #[arg(required = true)]
+        symbols: Vec<String>,
+        /// Unpacked kernel source tree.
+        #[arg(long = "source-dir")]
+        source_dir: PathBuf,
+        /// Final kernel config [default: <source-dir>/.config].
+        #[arg(long = "config")]
+        config: Option<PathBuf>,
+        /// Kernel architecture (ARCH) [default: read from the config header].
+        #[arg(long = "arch")]
+        arch: Option<String>,
+        /// How many levels of unmet dependencies to follow.
+        #[arg(long = "depth", default_value = "3")]
+        depth: usize,
+    },
+}
+
+fn download_kernel(downloader: &Downloader, args: &Args) -> anyhow::Result<PathBuf> {
+    println!("Downloading kernel source {}...", args.kernel);
+
+    let tarball = downloader.get(
+        &args.kernel,
+        &args.kernel.tarball_path(),
+        args.signature.as_deref(),
+    )?;
+
+    println!("Kernel source downloaded and verified successfully");
+    Ok(tarball)
 }
 
-fn download_kernel(file_name: &str) -> anyhow::Result<()> {
-    println!("Downloading kernel source...");
+/// Unpacks the base release of the series and applies the stable patch
+/// to reach `version`, which avoids downloading a whole tarball
+/// for every point release.
+fn patch_kernel(downloader: &Downloader, version: &Version, to: &Path) -> anyhow::Result<()> {
+    let base = version.base();
+    let patch_path = version.patch_path().unwrap();
+
+    println!(
+        "Downloading kernel source {} and patch to {}...",
+        base, version
+    );
 
-    let mut file = File::create(file_name)?;
+    let tarball = downloader.get(&base, &base.tarball_path(), None)?;
+    let patch = downloader.get(version, &patch_path, None)?;
 
-    reqwest::blocking::get(LATEST)?
-        .error_for_status()?
-        .copy_to(&mut file)?;
+    println!("Kernel source downloaded and verified successfully");
+
+    unpack_kernel(&tarball, to)?;
+
+    println!("Applying patch-{}...", version);
+    patch::apply(to, &mut XzDecoder::new(File::open(patch)?), 1)?;
 
-    println!("Kernel source downloaded successfully");
     Ok(())
 }
 
-fn compile(arch: &str, cross: Option<String>, img: &str) -> anyhow::Result<()> {
-    let arch_arg = format!("ARCH={}", arch);
-    let cross_arg = cross.map(|v| format!("CROSS_COMPILE={}", v));
+/// Unpacks a kernel tarball into the new directory `to`. A tree left behind
+/// by an earlier failed build is removed first; other existing directories
+/// are left alone.
+fn unpack_kernel(tarball: &Path, to: &Path) -> anyhow::Result<()> {
+    check_unpack_dir(to)?;
+
+    if remove_unpacked(to)? {
+        println!(
+            "Removing kernel source {} left by an earlier build",
+            to.display()
+        );
+    }
 
-    let mut defconfig = no_stdin("make");
-    defconfig.arg(&arch_arg).arg("defconfig");
+    fs::create_dir(to).with_context(|| format!("failed to create {}", to.display()))?;
+
+    // Written first so that a tree from an interrupted unpack is recognized too.
+    fs::write(to.join(UNPACKED_MARKER), "")?;
+
+    extract::unpack(tarball, to)?;
+
+    println!("Kernel source unpacked successfully");
+    Ok(())
+}
+
+/// Fails if `dir` exists but was not unpacked by us, so that it is never
+/// overwritten or removed.
+fn check_unpack_dir(dir: &Path) -> anyhow::Result<()> {
+    if dir.exists() && !dir.join(UNPACKED_MARKER).exists() {
+        bail!(
+            "{} already exists, remove it or build from it with --source-dir",
+            dir.display()
+        );
+    }
+
+    Ok(())
+}
+
+/// Removes `dir` if it is a tree unpacked by us, returning whether it was.
+fn remove_unpacked(dir: &Path) -> anyhow::Result<bool> {
+    if !dir.join(UNPACKED_MARKER).exists() {
+        return Ok(false);
+    }
+
+    fs::remove_dir_all(dir).with_context(|| format!("failed to remove {}", dir.display()))?;
+    Ok(true)
+}
+
+/// Runs `patch` to build the tree at `dir` incrementally, returning whether
+/// it succeeded. A partial tree is removed on failure so that the caller can
+/// fall back to the full tarball.
+fn try_incremental(
+    dir: &Path,
+    patch: impl FnOnce(&Path) -> anyhow::Result<()>,
+) -> anyhow::Result<bool> {
+    check_unpack_dir(dir)?;
+
+    match patch(dir) {
+        Ok(()) => Ok(true),
+        Err(e) => {
+            println!(
+                "Incremental update failed, falling back to full tarball: {:#}",
+                e
+            );
+
+            remove_unpacked(dir)?;
+            Ok(false)
+        }
+    }
+}
+
+/// Applies the common, per-architecture and per-target patch series
+/// followed by any extra patch directories.
+fn apply_patches(args: &Args, src: &Path, target: &Target) -> anyhow::Result<()> {
+    let mut dirs = vec![
+        args.patches.clone(),
+        args.patches.join(&target.arch),
+        args.patches.join(&target.name),
+    ];
+    dirs.dedup();
+
+    dirs.retain(|dir| dir.is_dir());
+    dirs.extend(args.extra_patches.iter().cloned());
+
+    let mut applied = 0;
+    for dir in dirs {
+        applied += patch::apply_series(src, &dir)?;
+    }
+
+    if applied > 0 {
+        println!("{} patches applied successfully", applied);
+    }
+
+    Ok(())
+}
+
+fn configure(src: &Path, config: &Config, target: &Target) -> anyhow::Result<()> {
+    let mut defconfig = make(src, target);
+    defconfig.arg("defconfig");
 
     if !defconfig.spawn()?.wait()?.success() {
         bail!("make defconfig failed");
     }
 
-    let mut mod2noconfig = no_stdin("make");
-    mod2noconfig.arg(&arch_arg).arg("mod2noconfig");
+    let mut mod2noconfig = make(src, target);
+    mod2noconfig.arg("mod2noconfig");
 
     if !mod2noconfig.spawn()?.wait()?.success() {
         bail!("make mod2noconfig failed");
@@ -884,36 +381,50 @@ fn compile(arch: &str, cross: Option<String>, img: &str) -> anyhow::Result<()> {
         let mut file = File::options()
             .truncate(false)
             .append(true)
-            .open(".config")?;
+            .open(src.join(".config"))?;
 
-        file.write_all(CONFIG.as_bytes())?;
+        file.write_all(config.to_fragment().as_bytes())?;
     }
 
-    let mut olddefconfig = no_stdin("make");
-    olddefconfig.arg(&arch_arg).arg("olddefconfig");
+    let mut olddefconfig = make(src, target);
+    olddefconfig.arg("olddefconfig");
 
     if !olddefconfig.spawn()?.wait()?.success() {
         bail!("make olddefconfig failed");
     }
 
-    let mut make = no_stdin("make");
-    make.arg(&arch_arg);
+    Ok(())
+}
 
-    if let Some(cross_compile) = cross_arg {
-        make.arg(cross_compile);
-    }
+/// Reports requested options that didn't make it into the final `.config`.
+/// Returns whether all of them did.
+fn check_config(src: &Path, config: &Config) -> anyhow::Result<bool> {
+    let mismatches = config::check(config, &Config::load(&src.join(".config"))?);
 
-    make.arg(img);
+    if mismatches.is_empty() {
+        println!("All requested config options are set");
+        return Ok(true);
+    }
 
-    // raspberry pi
-    if arch == "arm64" {
-        make.arg("dtbs");
+    println!(
+        "{} requested config options were not applied:",
+        mismatches.len()
+    );
+    for mismatch in &mismatches {
+        println!("  {}", mismatch);
     }
 
-    make.arg("modules")
+    Ok(false)
+}
+
+fn compile(src: &Path, target: &Target) -> anyhow::Result<()> {
+    let mut build = make(src, target);
+    build
+        .arg(&target.image)
+        .args(&target.make_targets)
         .arg("-j".to_owned() + &num_cpus::get().to_string());
 
-    if !make.spawn()?.wait()?.success() {
+    if !build.spawn()?.wait()?.success() {
         bail!("make failed");
     }
 
@@ -921,89 +432,679 @@ fn compile(arch: &str, cross: Option<String>, img: &str) -> anyhow::Result<()> {
 }
 
 fn main() -> anyhow::Result<()> {
-    let args = Args::parse();
-
-    let arch = String::from(match args.arch.as_str() {
-        "x86_64" => "x86_64",
-        "rpi" => "arm64",
-        _ => bail!("invalid architecture (supported: x86_64 rpi)"),
-    });
-
-    let cross = match args.arch.as_str() {
-        "x86_64" => None,
-        "rpi" => Some(String::from("aarch64-linux-gnu-")),
-        _ => bail!("invalid architecture (supported: x86_64 rpi)"),
+    let mut args = Args::parse();
+
+    match args.command.take() {
+        Some(Cmd::CheckUpdate {
+            kernel,
+            lockfile,
+            releases_url,
+        }) => {
+            let kernel = match lockfile {
+                Some(lockfile) => fs::read_to_string(lockfile)?.trim().parse()?,
+                None => kernel,
+            };
+
+            if check_update(&kernel, &releases_url)? {
+                process::exit(1);
+            }
+
+            Ok(())
+        }
+        Some(Cmd::Lint {
+            files,
+            config_dir,
+            strict,
+        }) => {
+            // The merged fragments of the targets are only checked
+            // along with the whole config directory.
+            let (files, config_dir) = if files.is_empty() {
+                (config::find(&config_dir)?, Some(config_dir))
+            } else {
+                (files, None)
+            };
+
+            if !lint(&files, config_dir.as_deref(), strict)? {
+                process::exit(1);
+            }
+
+            Ok(())
+        }
+        Some(Cmd::Targets { targets_files }) => list_targets(&targets_files),
+        Some(Cmd::Profiles { config_dir }) => list_profiles(&config_dir),
+        Some(Cmd::Minimize {
+            manifests,
+            source_dir,
+            arch,
+            fragment,
+            config_dir,
+            output,
+        }) => {
+            let fragment = fragment
+                .unwrap_or_else(|| config_dir.join("arch").join(format!("{}.config", arch)));
+            minimize(&manifests, &source_dir, &arch, &fragment, &output)
+        }
+        Some(Cmd::Audit { config, arch }) => {
+            if !audit(&config, arch)? {
+                process::exit(1);
+            }
+
+            Ok(())
+        }
+        Some(Cmd::ConfigDiff {
+            old,
+            new,
+            target,
+            build_args,
+        }) => config_diff(&old, &new, target.as_deref(), &build_args),
+        Some(Cmd::Explain {
+            symbols,
+            source_dir,
+            config,
+            arch,
+            depth,
+        }) => {
+            let config = config.unwrap_or_else(|| source_dir.join(".config"));
+            explain(&symbols, &source_dir, &config, arch, depth)
+        }
+        None => build(args),
+    }
+}
+
+/// Prints the problems found in config fragments. Returns whether they passed.
+fn lint(files: &[PathBuf], config_dir: Option<&Path>, strict: bool) -> anyhow::Result<bool> {
+    let mut errors = 0;
+    let mut warnings = 0;
+
+    for file in files {
+        let text = fs::read_to_string(file)
+            .with_context(|| format!("failed to read {}", file.display()))?;
+
+        for diagnostic in fragment::parse(file, &text).1 {
+            println!("{}", diagnostic);
+
+            match diagnostic.severity {
+                fragment::Severity::Error => errors += 1,
+                fragment::Severity::Warning => warnings += 1,
+            }
+        }
+    }
+
+    let mut targets = 0;
+
+    if let Some(config_dir) = config_dir {
+        for target in Registry::load(&[])?.iter() {
+            let profiles = config::select_profiles(config_dir, &target.profiles)?;
+
+            for diagnostic in config::redundant(config_dir, target, &profiles)? {
+                println!("{}", diagnostic);
+                warnings += 1;
+            }
+
+            targets += 1;
+        }
+    }
+
+    println!(
+        "Checked {} fragments and {} targets: {} errors, {} warnings",
+        files.len(),
+        targets,
+        errors,
+        warnings
+    );
+
+    Ok(errors == 0 && (!strict || warnings == 0))
+}
+
+/// Prints the known targets and how they are built.
+fn list_targets(targets_files: &[PathBuf]) -> anyhow::Result<()> {
+    for target in Registry::load(targets_files)?.iter() {
+        println!("{}: {}", target.name, target.description);
+        println!("  ARCH={}", target.arch);
+
+        if let Some(cross_compile) = &target.cross_compile {
+            println!("  CROSS_COMPILE={}", cross_compile);
+        }
+
+        println!(
+            "  make {}",
+            [&target.image]
+                .into_iter()
+                .chain(&target.make_targets)
+                .map(String::as_str)
+                .collect::<Vec<_>>()
+                .join(" ")
+        );
+
+        for fragment in &target.fragments {
+            println!("  fragment {}", fragment.display());
+        }
+
+        println!("  profiles {}", target.profiles.join(" "));
+
+        for (dtb, to) in target.dtbs.iter().chain(&target.artifacts) {
+            println!("  {} -> {}", dtb, to);
+        }
+
+        for name in target.files.keys() {
+            println!("  writes {}", name);
+        }
+    }
+
+    Ok(())
+}
+
+/// Prints the available profiles along with the options they set.
+fn list_profiles(config_dir: &Path) -> anyhow::Result<()> {
+    for profile in config::profiles(config_dir)? {
+        println!("{}: {}", profile.name, profile.description);
+
+        for (symbol, value) in Config::load(&profile.path)?.iter() {
+            println!("  CONFIG_{}={}", symbol, value);
+        }
+
+        for (arch, path) in &profile.arch_paths {
+            println!("  on {}:", arch);
+
+            for (symbol, value) in Config::load(path)?.iter() {
+                println!("    CONFIG_{}={}", symbol, value);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Writes a minimal version of `fragment` for the hardware in `manifests`
+/// and reports what was removed.
+fn minimize(
+    manifests: &[PathBuf],
+    source_dir: &Path,
+    arch: &str,
+    fragment: &Path,
+    output: &Path,
+) -> anyhow::Result<()> {
+    let mut manifest = minimize::Manifest::default();
+    for path in manifests {
+        manifest.merge_file(path)?;
+    }
+
+    println!(
+        "Reading Kconfig and Makefiles of {}...",
+        source_dir.display()
+    );
+    let kconfig = Kconfig::parse(source_dir, kconfig::srcarch(arch))?;
+    let original = Config::load(fragment)?;
+
+    let minimized = minimize::minimize(source_dir, &kconfig, &original, &manifest)?;
+
+    for entry in &minimized.unresolved {
+        println!("Warning: {} not found in the kernel tree", entry);
+    }
+    for symbol in &minimized.added {
+        println!("Adding CONFIG_{} required by the manifest", symbol);
+    }
+
+    fs::write(
+        output,
+        format!(
+            "# Minimized from {} for {}.\n\n{}",
+            fragment.display(),
+            manifests
+                .iter()
+                .map(|path| path.display().to_string())
+                .collect::<Vec<_>>()
+                .join(", "),
+            minimized.config.to_fragment()
+        ),
+    )?;
+
+    println!(
+        "Wrote {} with {} of {} options ({} removed, {} added)",
+        output.display(),
+        minimized.config.iter().count(),
+        original.iter().count(),
+        minimized.removed.len(),
+        minimized.added.len()
+    );
+
+    match minimized.object_bytes {
+        Some(bytes) => println!(
+            "Removed options account for {} KiB of compiled objects",
+            bytes / 1024
+        ),
+        None => println!("Build the source tree with the current config to estimate size savings"),
+    }
+
+    Ok(())
+}
+
+/// Prints the result of every hardening check. Returns whether all of them passed.
+fn audit(config_path: &Path, arch: Option<String>) -> anyhow::Result<bool> {
+    let arch = kconfig::config_arch_or(arch, config_path)?;
+
+    let config = Config::load(config_path)?;
+    let findings = audit::audit(&config, &arch);
+
+    for finding in &findings {
+        println!("{}", finding);
+    }
+
+    let passed = findings.iter().filter(|finding| finding.passed).count();
+    println!("{} of {} checks passed", passed, findings.len());
+
+    Ok(passed == findings.len())
+}
+
+/// Prints the differences between two effective kernel configurations.
+fn config_diff(
+    old: &str,
+    new: &str,
+    target: Option<&str>,
+    build_args: &[String],
+) -> anyhow::Result<()> {
+    let (old_path, old_text) = effective_config(old, target, build_args)?;
+    let (new_path, new_text) = effective_config(new, target, build_args)?;
+
+    let diff = diff::Diff::new(&old_path, &old_text, &new_path, &new_text)?;
+    let (added, removed, changed) = diff.counts();
+
+    println!("Comparing {} with {}", old, new);
+    println!();
+    print!("{}", diff);
+    println!(
+        "{} new, {} removed, {} changed options",
+        added, removed, changed
+    );
+
+    Ok(())
+}
+
+/// Reads a `.config` file, the `.config` of a kernel tree or `config-<target>` of an
+/// output directory, or configures a kernel version with `build_args` to get its `.config`.
+fn effective_config(
+    operand: &str,
+    target: Option<&str>,
+    build_args: &[String],
+) -> anyhow::Result<(PathBuf, String)> {
+    let path = Path::new(operand);
+
+    let path = if path.is_file() {
+        path.to_path_buf()
+    } else if path.is_dir() {
+        match target {
+            _ if path.join(".config").exists() => path.join(".config"),
+            Some(target) => path.join(format!("config-{}", target)),
+            None => bail!(
+                "{} has no .config, use --architecture to compare config-<target>",
+                path.display()
+            ),
+        }
+    } else {
+        let version = operand.parse::<Version>().with_context(|| {
+            format!(
+                "{} is neither a file, a directory nor a kernel version",
+                operand
+            )
+        })?;
+        let target = target.context("comparing kernel versions requires --architecture")?;
+
+        let mut args = Args::try_parse_from(
+            [
+                env!("CARGO_PKG_NAME"),
+                "--architecture",
+                target,
+                "--kernel",
+                &version.to_string(),
+            ]
+            .into_iter()
+            .chain(build_args.iter().map(String::as_str)),
+        )?;
+
+        let tree = configure_tree(&mut args)?;
+        let path = tree.src_dir.join(".config");
+        let text = fs::read_to_string(&path)?;
+
+        if tree.unpacked {
+            fs::remove_dir_all(&tree.src_dir)?;
+        }
+
+        return Ok((path, text));
     };
 
-    let img = String::from(match args.arch.as_str() {
-        "x86_64" => "bzImage",
-        "rpi" => "Image.gz",
-        _ => bail!("invalid architecture (supported: x86_64 rpi)"),
-    });
+    let text =
+        fs::read_to_string(&path).with_context(|| format!("failed to read {}", path.display()))?;
 
-    let file_name = Path::new(LATEST).file_name().unwrap().to_str().unwrap();
+    Ok((path, text))
+}
 
-    download_kernel(file_name)?;
+/// Prints the dependency chains keeping kernel options from being enabled.
+fn explain(
+    symbols: &[String],
+    source_dir: &Path,
+    config_path: &Path,
+    arch: Option<String>,
+    depth: usize,
+) -> anyhow::Result<()> {
+    let arch = kconfig::config_arch_or(arch, config_path)?;
 
-    let mut untar = no_stdin("tar");
-    untar.arg("xf").arg(file_name);
+    let kconfig = Kconfig::parse(source_dir, kconfig::srcarch(&arch))?;
+    let config = Config::load(config_path)?;
 
-    if !untar.spawn()?.wait()?.success() {
-        bail!("untar failed");
+    for symbol in symbols {
+        let symbol = symbol.strip_prefix("CONFIG_").unwrap_or(symbol);
+        print!("{}", kconfig.explain(&config, symbol, depth));
     }
 
-    println!("Kernel source unpacked successfully");
+    Ok(())
+}
+
+/// Reports newer releases of the series `kernel` belongs to
+/// and whether the series is end of life. Returns whether an update is due.
+fn check_update(kernel: &Version, releases_url: &str) -> anyhow::Result<bool> {
+    let releases = Releases::fetch(releases_url)?;
+
+    let series = match releases.series(kernel) {
+        Some(series) => series,
+        None => {
+            println!(
+                "Kernel {} is out of date: the {} series is no longer maintained",
+                kernel,
+                kernel.base()
+            );
+
+            if let Ok(stable) = releases.resolve(Channel::Stable) {
+                println!("Latest stable release: {}", stable);
+            }
+            if let Ok(longterm) = releases.resolve(Channel::Longterm) {
+                println!("Latest longterm release: {}", longterm);
+            }
+
+            return Ok(true);
+        }
+    };
+
+    let mut update = false;
+
+    if series.current > *kernel {
+        println!(
+            "Kernel {} is out of date: {} {} is available",
+            kernel, series.moniker, series.current
+        );
+        update = true;
+    } else {
+        println!("Kernel {} is the latest {} release", kernel, series.moniker);
+    }
+
+    if series.iseol {
+        println!(
+            "The {} series is end of life, move to a maintained series",
+            kernel.base()
+        );
+        update = true;
+    }
+
+    Ok(update)
+}
+
+/// A kernel source tree configured for a target.
+struct Configured {
+    target: Target,
+    src_dir: PathBuf,
+    /// Whether we unpacked the tree and may delete it.
+    unpacked: bool,
+    fragments: Vec<PathBuf>,
+    config: Config,
+    overrides: Config,
+}
+
+/// Obtains, patches and configures the kernel source tree described by `args`.
+fn configure_tree(args: &mut Args) -> anyhow::Result<Configured> {
+    // Required unless a subcommand is given.
+    let target = Registry::load(&args.targets_files)?
+        .get(args.arch.as_deref().unwrap())?
+        .clone();
+
+    if let Some(channel) = args.channel {
+        args.kernel = Releases::fetch(&args.releases_url)?.resolve(channel)?;
+        println!("Resolved {} channel to kernel {}", channel, args.kernel);
+    }
+
+    let profiles = if args.profiles.is_empty() {
+        &target.profiles
+    } else {
+        &args.profiles
+    };
+    let profiles = config::select_profiles(&args.config_dir, profiles)?;
+    let fragments = config::fragments(&args.config_dir, &target, &profiles, &args.fragments);
+    for fragment in &fragments {
+        println!("Using config fragment {}", fragment.display());
+    }
 
-    let current_dir = env::current_dir()?;
-    env::set_current_dir(file_name.trim_end_matches(".tar.xz"))?;
+    let mut config = config::merge(&fragments)?;
+    let overrides = overrides(&args.set, &args.unset)?;
+
+    for (symbol, value) in overrides.iter() {
+        println!("Overriding CONFIG_{}={}", symbol, value);
+        config.set(symbol, value);
+    }
+
+    let (src_dir, unpacked) = if let Some(dir) = &args.source_dir {
+        println!("Using kernel source tree {}", dir.display());
+        (dir.clone(), false)
+    } else {
+        match &args.tarball {
+            Some(tarball) => {
+                let src_dir = PathBuf::from(tarball_stem(tarball)?);
+                unpack_kernel(tarball, &src_dir)?;
+
+                (src_dir, true)
+            }
+            None => {
+                let mirrors =
+                    Mirrors::load(&args.mirrors, args.mirror_file.as_deref(), args.retries)?;
+                let verifier = Verifier::new(&args.keyring, &args.trusted_keys)?;
+                let cache = Cache::new(args.cache_dir.clone())?;
+
+                let downloader = Downloader::new(
+                    &mirrors,
+                    &verifier,
+                    &cache,
+                    &args.kernel,
+                    args.checksums.as_deref(),
+                )?;
+
+                let src_dir = PathBuf::from(args.kernel.name());
+
+                // Patch the cached base release unless the full tarball is already cached
+                // or a signature for the full tarball was given.
+                let full_cached = downloader
+                    .path(&args.kernel, &args.kernel.tarball_path())?
+                    .exists();
+                let incremental = args.kernel.patch.is_some()
+                    && !args.full_tarball
+                    && !full_cached
+                    && args.signature.is_none();
+
+                let patched = incremental
+                    && try_incremental(&src_dir, |dir| {
+                        patch_kernel(&downloader, &args.kernel, dir)
+                    })?;
+
+                if !patched {
+                    let tarball = download_kernel(&downloader, args)?;
+                    unpack_kernel(&tarball, &src_dir)?;
+                }
+
+                (src_dir, true)
+            }
+        }
+    };
+
+    if unpacked {
+        apply_patches(args, &src_dir, &target)?;
+    } else {
+        println!("Not patching external source tree {}", src_dir.display());
+    }
+
+    println!("Configuring kernel...");
+    configure(&src_dir, &config, &target)?;
+
+    if !check_config(&src_dir, &config)? && args.strict {
+        bail!("requested config options were not applied (strict mode)");
+    }
+
+    Ok(Configured {
+        target,
+        src_dir,
+        unpacked,
+        fragments,
+        config,
+        overrides,
+    })
+}
+
+fn build(mut args: Args) -> anyhow::Result<()> {
+    let Configured {
+        target,
+        src_dir,
+        unpacked,
+        fragments,
+        config,
+        overrides,
+    } = configure_tree(&mut args)?;
 
     println!("Compiling kernel...");
-    compile(&arch, cross, &img)?;
+    compile(&src_dir, &target)?;
     println!("Kernel compiled successfully");
 
-    let kernel_path = format!("arch/{}/boot/{}", arch, img);
+    let kernel_path = format!("arch/{}/boot/{}", target.arch, target.image);
 
-    env::set_current_dir(current_dir)?;
+    fs::copy(
+        src_dir.join(kernel_path),
+        format!("vmlinuz-{}", target.name),
+    )?;
 
+    let version = kernel_version(&src_dir, &target)?;
+    fs::write(format!("version-{}", target.name), format!("{}\n", version))?;
+    fs::copy(src_dir.join(".config"), format!("config-{}", target.name))?;
+
+    save_defconfig(&src_dir, &target)?;
     fs::copy(
-        Path::new(file_name.trim_end_matches(".tar.xz")).join(kernel_path),
-        format!("vmlinuz-{}", args.arch),
+        src_dir.join("defconfig"),
+        format!("defconfig-{}", target.name),
     )?;
 
-    if args.arch.as_str() == "rpi" {
-        copy_file(
-            file_name,
-            "arch/arm64/boot/dts/broadcom/bcm2837-rpi-3-b.dtb",
-            "bcm2710-rpi-3-b.dtb",
-        )?;
-        copy_file(
-            file_name,
-            "arch/arm64/boot/dts/broadcom/bcm2837-rpi-3-b-plus.dtb",
-            "bcm2710-rpi-3-b-plus.dtb",
-        )?;
-        copy_file(
-            file_name,
-            "arch/arm64/boot/dts/broadcom/bcm2837-rpi-cm3-io3.dtb",
-            "bcm2710-rpi-cm3.dtb",
-        )?;
-        copy_file(
-            file_name,
-            "arch/arm64/boot/dts/broadcom/bcm2711-rpi-4-b.dtb",
-            "bcm2711-rpi-4-b.dtb",
-        )?;
+    fs::write(
+        format!("fragments-{}", target.name),
+        record_fragments(&fragments, &config, &version)?,
+    )?;
+    fs::write(
+        format!("overrides-{}", target.name),
+        format!(
+            "# Command line overrides for kernel {}\n{}",
+            version,
+            overrides.to_fragment()
+        ),
+    )?;
+    println!("Built kernel {}", version);
+
+    for (dtb, to) in &target.dtbs {
         copy_file(
-            file_name,
-            "arch/arm64/boot/dts/broadcom/bcm2837-rpi-zero-2-w.dtb",
-            "bcm2710-rpi-zero-2-w.dtb",
+            &src_dir,
+            &format!("arch/{}/boot/dts/{}", target.arch, dtb),
+            to,
         )?;
     }
 
-    fs::remove_file(file_name)?;
-    fs::remove_dir_all(file_name.trim_end_matches(".tar.xz"))?;
+    for (artifact, to) in &target.artifacts {
+        copy_file(&src_dir, artifact, to)?;
+    }
+
+    for (name, contents) in &target.files {
+        fs::write(name, contents)?;
+    }
+
+    // Never delete a source tree we didn't unpack ourselves.
+    if unpacked {
+        fs::remove_dir_all(src_dir)?;
+    }
+
+    Ok(())
+}
+
+/// Lists the fragments a kernel was configured from along with their digests,
+/// followed by the options requested from them and the command line overrides.
+fn record_fragments(
+    fragments: &[PathBuf],
+    config: &Config,
+    version: &str,
+) -> anyhow::Result<String> {
+    let mut record = format!(
+        "# Config fragments for kernel {} in merge order:\n",
+        version
+    );
+
+    for fragment in fragments {
+        record += &format!(
+            "# {} sha256:{}\n",
+            fragment.display(),
+            verify::sha256(fragment)?
+        );
+    }
+
+    record += "\n";
+    record += &config.to_fragment();
+
+    Ok(record)
+}
+
+/// Collects the `--set` and `--unset` options, rejecting options given in both.
+fn overrides(set: &[(String, String)], unset: &[String]) -> anyhow::Result<Config> {
+    let mut overrides = Config::default();
+
+    for (symbol, value) in set {
+        overrides.set(symbol, value);
+    }
+
+    for symbol in unset {
+        if let Some(value) = overrides.get(symbol) {
+            bail!("CONFIG_{} is both set to {} and unset", symbol, value);
+        }
+
+        overrides.set(symbol, "n");
+    }
+
+    Ok(overrides)
+}
+
+/// Writes a minimal `defconfig` for the configured tree at `src`.
+fn save_defconfig(src: &Path, target: &Target) -> anyhow::Result<()> {
+    let mut savedefconfig = make(src, target);
+    savedefconfig.arg("savedefconfig");
+
+    if !savedefconfig.spawn()?.wait()?.success() {
+        bail!("make savedefconfig failed");
+    }
 
     Ok(())
 }
 
+/// Returns the version of the kernel source tree at `src` as reported by its Makefile.
+fn kernel_version(src: &Path, target: &Target) -> anyhow::Result<String> {
+    let output = make(src, target)
+        .arg("-s")
+        .arg("kernelversion")
+        .stderr(Stdio::inherit())
+        .output()?;
+
+    if !output.status.success() {
+        bail!("make kernelversion failed");
+    }
+
+    Ok(String::from_utf8(output.stdout)?.trim().to_string())
+}
+
 fn no_stdin<S: AsRef<OsStr>>(program: S) -> Command {
     let mut cmd = Command::new(program);
     cmd.stdin(Stdio::null());
@@ -1011,6 +1112,95 @@ fn no_stdin<S: AsRef<OsStr>>(program: S) -> Command {
     cmd
 }
 
-fn copy_file<T: AsRef<Path>>(base: &str, path: &str, to: T) -> io::Result<u64> {
-    fs::copy(Path::new(base.trim_end_matches(".tar.xz")).join(path), to)
+/// Returns a `make` invocation in the tree at `src` for the architecture
+/// and cross compiler of `target`.
+fn make(src: &Path, target: &Target) -> Command {
+    let mut cmd = no_stdin("make");
+    cmd.current_dir(src).arg(format!("ARCH={}", target.arch));
+
+    if let Some(cross_compile) = &target.cross_compile {
+        cmd.arg(format!("CROSS_COMPILE={}", cross_compile));
+    }
+
+    cmd
+}
+
+/// Returns the file name of a tarball without its `.tar.*` extension.
+fn tarball_stem(tarball: &Path) -> anyhow::Result<&str> {
+    let file_name = tarball
+        .file_name()
+        .and_then(|name| name.to_str())
+        .context("invalid tarball path")?;
+
+    Ok(match file_name.rsplit_once(".tar") {
+        Some((stem, _)) => stem,
+        None => file_name.trim_end_matches(".tgz"),
+    })
+}
+
+fn copy_file<T: AsRef<Path>>(base: &Path, path: &str, to: T) -> io::Result<u64> {
+    fs::copy(base.join(path), to)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("rustkrazy-main-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn incremental_keeps_unmarked_tree() {
+        let dir = temp_dir("unmarked");
+        let src = dir.join("linux-6.1.1");
+        fs::create_dir(&src).unwrap();
+        fs::write(src.join("Makefile"), "").unwrap();
+
+        let mut called = false;
+        let err = try_incremental(&src, |_| {
+            called = true;
+            bail!("patch failed")
+        })
+        .unwrap_err();
+
+        assert!(format!("{:#}", err).contains("already exists"));
+        assert!(!called);
+        assert!(src.join("Makefile").exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn incremental_removes_partial_tree() {
+        let dir = temp_dir("partial");
+        let src = dir.join("linux-6.1.1");
+
+        let patched = try_incremental(&src, |to| {
+            fs::create_dir(to)?;
+            fs::write(to.join(UNPACKED_MARKER), "")?;
+            bail!("patch failed")
+        })
+        .unwrap();
+
+        assert!(!patched);
+        assert!(!src.exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn incremental_succeeds() {
+        let dir = temp_dir("success");
+        let src = dir.join("linux-6.1.1");
+
+        assert!(try_incremental(&src, |to| Ok(fs::create_dir(to)?)).unwrap());
+        assert!(src.exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
 }
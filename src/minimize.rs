@@ -0,0 +1,515 @@
+use crate::config::Config;
+use crate::kconfig::Kconfig;
+
+use anyhow::{bail, Context};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Directories of a kernel tree that never hold drivers.
+const SKIP_DIRS: &[&str] = &["Documentation", "scripts", "tools", "samples", "usr"];
+
+/// The hardware a kernel has to support.
+#[derive(Debug, Default)]
+pub struct Manifest {
+    /// Config symbols without the `CONFIG_` prefix.
+    pub symbols: Vec<String>,
+    /// Module or driver names, e.g. from `lsmod` or `/sys/module`.
+    pub modules: Vec<String>,
+    /// Device tree compatible strings.
+    pub compatibles: Vec<String>,
+}
+
+impl Manifest {
+    /// Reads manifest entries from a file. Accepted lines are
+    /// `CONFIG_X[=value]`, `module <name>`, `driver <name>`, `compatible <string>`,
+    /// `lsmod` output, `/sys/module/<name>` and `/sys/bus/<bus>/drivers/<name>` paths
+    /// and bare `vendor,device` compatibles, e.g. from
+    /// `/sys/firmware/devicetree/base/compatible` (NUL separated).
+    pub fn merge_file(&mut self, path: &Path) -> anyhow::Result<()> {
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("failed to read manifest {}", path.display()))?;
+
+        for (i, line) in text.lines().enumerate() {
+            for entry in line.split('\0') {
+                self.add(entry.trim()).with_context(|| {
+                    format!("{}:{}: invalid manifest entry", path.display(), i + 1)
+                })?;
+            }
+        }
+
+        Ok(())
+    }
+
+    fn add(&mut self, entry: &str) -> anyhow::Result<()> {
+        let words = entry.split_whitespace().collect::<Vec<_>>();
+
+        match words.as_slice() {
+            [] => {}
+            [comment, ..] if comment.starts_with('#') => {}
+            ["Module", "Size", "Used", "by"] => {}
+            ["module" | "driver", name] => self.modules.push(name.to_string()),
+            ["compatible", compatible] => self
+                .compatibles
+                .push(compatible.trim_matches('"').to_string()),
+            // lsmod: name, size, use count and users.
+            [name, size, ..] if size.parse::<u64>().is_ok() => self.modules.push(name.to_string()),
+            [word] => {
+                if let Some(symbol) = word.strip_prefix("CONFIG_") {
+                    let symbol = symbol.split_once('=').map_or(symbol, |(symbol, _)| symbol);
+                    self.symbols.push(symbol.to_string());
+                } else if let Some(module) = word.strip_prefix("/sys/module/") {
+                    self.modules
+                        .push(module.split('/').next().unwrap().to_string());
+                } else if let Some((_, driver)) = word
+                    .strip_prefix("/sys/bus/")
+                    .and_then(|rest| rest.split_once("/drivers/"))
+                {
+                    self.modules
+                        .push(driver.split('/').next().unwrap().to_string());
+                } else if word.contains(',') && !word.contains('/') {
+                    self.compatibles.push(word.to_string());
+                } else {
+                    bail!("unrecognized entry {:?}", word);
+                }
+            }
+            _ => bail!("unrecognized entry {:?}", entry),
+        }
+
+        Ok(())
+    }
+}
+
+/// Which config symbols build which objects, read from the Makefiles of a kernel tree.
+#[derive(Debug, Default)]
+struct Kbuild {
+    /// Objects added with `obj-$(CONFIG_X)`, `obj-y` or `obj-m`
+    /// and the symbol they depend on, if any.
+    objects: HashMap<PathBuf, Option<String>>,
+    /// Subdirectories that are only descended into if a symbol is set.
+    dirs: HashMap<PathBuf, String>,
+    /// Members of composite objects (`foo-y += bar.o`) and the object they are linked into,
+    /// along with the symbol the member depends on, if any.
+    members: HashMap<PathBuf, (PathBuf, Option<String>)>,
+}
+
+impl Kbuild {
+    fn load(srctree: &Path) -> anyhow::Result<Self> {
+        let mut kbuild = Self::default();
+        kbuild.load_dir(srctree, Path::new(""))?;
+
+        Ok(kbuild)
+    }
+
+    fn load_dir(&mut self, srctree: &Path, dir: &Path) -> anyhow::Result<()> {
+        for entry in fs::read_dir(srctree.join(dir))? {
+            let entry = entry?;
+            let name = entry.file_name();
+            let rel = dir.join(&name);
+
+            if entry.file_type()?.is_dir() {
+                if !(dir.as_os_str().is_empty() && SKIP_DIRS.iter().any(|skip| name == *skip)) {
+                    self.load_dir(srctree, &rel)?;
+                }
+            } else if name == "Makefile" || name == "Kbuild" {
+                let text = fs::read_to_string(entry.path())?;
+                self.parse_makefile(dir, &text);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn parse_makefile(&mut self, dir: &Path, text: &str) {
+        let text = text.replace("\\\n", " ");
+
+        for line in text.lines() {
+            let Some((lhs, rhs)) = line
+                .split_once("+=")
+                .or_else(|| line.split_once(":="))
+                .or_else(|| line.split_once('='))
+            else {
+                continue;
+            };
+
+            let lhs = lhs.trim();
+            let rhs = rhs.split('#').next().unwrap();
+            let Some((name, cond)) = lhs.rsplit_once('-') else {
+                continue;
+            };
+
+            let symbol = match cond {
+                "y" | "m" | "objs" => None,
+                _ => match cond
+                    .strip_prefix("$(CONFIG_")
+                    .and_then(|cond| cond.strip_suffix(')'))
+                {
+                    Some(symbol) => Some(symbol.to_string()),
+                    None => continue,
+                },
+            };
+
+            for word in rhs.split_whitespace() {
+                if name == "obj" {
+                    if let Some(subdir) = word.strip_suffix('/') {
+                        if let Some(symbol) = &symbol {
+                            self.dirs.insert(dir.join(subdir), symbol.clone());
+                        }
+                    } else if word.ends_with(".o") {
+                        self.objects.insert(dir.join(word), symbol.clone());
+                    }
+                } else if word.ends_with(".o") && !name.contains(['$', '(', ' ']) {
+                    self.members.insert(
+                        dir.join(word),
+                        (dir.join(format!("{}.o", name)), symbol.clone()),
+                    );
+                }
+            }
+        }
+    }
+
+    /// Returns the symbols needed to build an object: its own, that of the composite
+    /// object it is part of and those of the directories leading to it.
+    fn symbols(&self, object: &Path) -> Vec<String> {
+        let mut symbols = Vec::new();
+        let mut object = object.to_path_buf();
+
+        // Follow nested composite objects, guarding against cycles.
+        for _ in 0..4 {
+            match self.members.get(&object) {
+                Some((composite, symbol)) if *composite != object => {
+                    symbols.extend(symbol.clone());
+                    object = composite.clone();
+                }
+                _ => break,
+            }
+        }
+
+        if let Some(Some(symbol)) = self.objects.get(&object) {
+            symbols.push(symbol.clone());
+        }
+
+        for dir in object.ancestors().skip(1) {
+            if let Some(symbol) = self.dirs.get(dir) {
+                symbols.push(symbol.clone());
+            }
+        }
+
+        symbols
+    }
+
+    /// Returns the objects built for a module name.
+    fn module(&self, name: &str) -> Vec<&Path> {
+        let name = normalize(name);
+
+        self.objects
+            .keys()
+            .filter(|object| {
+                object
+                    .file_stem()
+                    .is_some_and(|stem| normalize(&stem.to_string_lossy()) == name)
+            })
+            .map(PathBuf::as_path)
+            .collect()
+    }
+}
+
+/// The result of minimizing a fragment.
+#[derive(Debug)]
+pub struct Minimized {
+    /// The minimal fragment.
+    pub config: Config,
+    /// Options of the original fragment that were dropped.
+    pub removed: Vec<String>,
+    /// Symbols the manifest requires that the original fragment didn't set.
+    pub added: Vec<String>,
+    /// Manifest entries that couldn't be mapped to a config symbol of the tree.
+    pub unresolved: Vec<String>,
+    /// Total size of the compiled objects only built because of removed options,
+    /// if the tree has been built.
+    pub object_bytes: Option<u64>,
+}
+
+/// Reduces `fragment` to the options needed by the hardware in `manifest`:
+/// the required symbols and everything they depend on. Disabled options and
+/// non-boolean values are kept as they are.
+pub fn minimize(
+    srctree: &Path,
+    kconfig: &Kconfig,
+    fragment: &Config,
+    manifest: &Manifest,
+) -> anyhow::Result<Minimized> {
+    let kbuild = Kbuild::load(srctree)?;
+
+    let mut required = manifest.symbols.clone();
+    let mut unresolved = Vec::new();
+    let mut strings = Vec::new();
+
+    for module in &manifest.modules {
+        let objects = kbuild.module(module);
+        if objects.is_empty() {
+            // Driver names often differ from module names, look for them in the sources.
+            strings.push(module.clone());
+        }
+
+        for object in objects {
+            required.extend(kbuild.symbols(object));
+        }
+    }
+
+    strings.extend(manifest.compatibles.iter().cloned());
+
+    let found = find_strings(srctree, &strings)?;
+    for string in &strings {
+        match found.get(string) {
+            Some(objects) => {
+                for object in objects {
+                    required.extend(kbuild.symbols(object));
+                }
+            }
+            None => unresolved.push(string.clone()),
+        }
+    }
+
+    required.sort();
+    required.dedup();
+    required.retain(|symbol| {
+        let defined = kconfig.defines(symbol);
+        if !defined {
+            unresolved.push(format!("CONFIG_{}", symbol));
+        }
+
+        defined
+    });
+
+    let closure = kconfig.dependencies(required.iter().map(String::as_str));
+
+    let mut config = Config::default();
+    let mut removed = Vec::new();
+
+    for (symbol, value) in fragment.iter() {
+        let boolean = value == "y" || value == "m";
+
+        if !boolean || closure.contains(symbol) {
+            config.set(symbol, value);
+        } else {
+            removed.push(symbol.to_string());
+        }
+    }
+
+    let mut added = Vec::new();
+    for symbol in &required {
+        if config.get(symbol).is_none() {
+            config.set(symbol, "y");
+            added.push(symbol.clone());
+        }
+    }
+
+    let removed_set = removed.iter().map(String::as_str).collect::<HashSet<_>>();
+    let mut object_bytes = None;
+
+    for object in kbuild.objects.keys() {
+        let gated = kbuild
+            .symbols(object)
+            .iter()
+            .any(|symbol| removed_set.contains(symbol.as_str()));
+
+        if let Ok(metadata) = fs::metadata(srctree.join(object)) {
+            let total = object_bytes.get_or_insert(0);
+            if gated {
+                *total += metadata.len();
+            }
+        }
+    }
+
+    Ok(Minimized {
+        config,
+        removed,
+        added,
+        unresolved,
+        object_bytes,
+    })
+}
+
+/// Finds the C source files containing the given string literals
+/// and returns the objects they are compiled into.
+fn find_strings(
+    srctree: &Path,
+    strings: &[String],
+) -> anyhow::Result<HashMap<String, Vec<PathBuf>>> {
+    let mut found = HashMap::new();
+
+    if strings.is_empty() {
+        return Ok(found);
+    }
+
+    let needles = strings
+        .iter()
+        .map(|string| (string, format!("\"{}\"", string)))
+        .collect::<Vec<_>>();
+
+    let mut stack = vec![PathBuf::new()];
+    while let Some(dir) = stack.pop() {
+        for entry in fs::read_dir(srctree.join(&dir))? {
+            let entry = entry?;
+            let name = entry.file_name();
+            let rel = dir.join(&name);
+
+            if entry.file_type()?.is_dir() {
+                if !(dir.as_os_str().is_empty() && SKIP_DIRS.iter().any(|skip| name == *skip)) {
+                    stack.push(rel);
+                }
+            } else if rel.extension().is_some_and(|ext| ext == "c") {
+                let Ok(text) = fs::read_to_string(entry.path()) else {
+                    continue;
+                };
+
+                for (string, needle) in &needles {
+                    if text.contains(needle.as_str()) {
+                        found
+                            .entry((*string).clone())
+                            .or_insert_with(Vec::new)
+                            .push(rel.with_extension("o"));
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(found)
+}
+
+/// Module names use `_` where file names may use `-`.
+fn normalize(name: &str) -> String {
+    name.replace('-', "_")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture() -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/minimize")
+    }
+
+    #[test]
+    fn manifest_entries() {
+        let mut manifest = Manifest::default();
+        manifest
+            .merge_file(&fixture().with_extension("manifest"))
+            .unwrap();
+
+        for entry in [
+            "module e1000e",
+            "/sys/module/xhci_hcd",
+            "/sys/module/snd/parameters/slots",
+            "/sys/bus/pci/drivers/ahci",
+            "CONFIG_FOO_PTP",
+        ] {
+            manifest.add(entry).unwrap();
+        }
+
+        assert_eq!(manifest.symbols, ["EXTRA", "FOO_PTP"]);
+        assert_eq!(
+            manifest.modules,
+            [
+                "foo_eth",
+                "missing-drv",
+                "e1000e",
+                "xhci_hcd",
+                "snd",
+                "ahci"
+            ]
+        );
+        assert_eq!(
+            manifest.compatibles,
+            ["acme,baz-uart", "acme,board", "acme,soc"]
+        );
+
+        for entry in ["e1000e", "/dev/sda", "module"] {
+            assert!(manifest.add(entry).is_err(), "{}", entry);
+        }
+    }
+
+    #[test]
+    fn kbuild_symbols() {
+        let kbuild = Kbuild::load(&fixture()).unwrap();
+
+        let objects = kbuild.module("foo_eth");
+        assert_eq!(objects, [Path::new("drivers/net/foo/foo-eth.o")]);
+
+        // The object itself, then the directories leading to it.
+        assert_eq!(
+            kbuild.symbols(objects[0]),
+            ["FOO_ETH", "NET_VENDOR_FOO", "NET"]
+        );
+        // A member of a composite object needs that object too.
+        assert_eq!(
+            kbuild.symbols(Path::new("drivers/net/foo/foo-ptp.o")),
+            ["FOO_PTP", "FOO_ETH", "NET_VENDOR_FOO", "NET"]
+        );
+        assert_eq!(
+            kbuild.symbols(Path::new("drivers/net/foo/foo-phy.o")),
+            ["FOO_ETH", "NET_VENDOR_FOO", "NET"]
+        );
+        assert_eq!(
+            kbuild.symbols(Path::new("drivers/tty/baz.o")),
+            ["SERIAL_BAZ"]
+        );
+
+        // Documentation/ is never read.
+        assert!(kbuild.module("example").is_empty());
+    }
+
+    #[test]
+    fn keeps_what_the_manifest_needs() {
+        let srctree = fixture();
+
+        let mut manifest = Manifest::default();
+        manifest
+            .merge_file(&srctree.with_extension("manifest"))
+            .unwrap();
+
+        let kconfig = Kconfig::parse(&srctree, "x86").unwrap();
+
+        let mut fragment = Config::default();
+        fragment
+            .merge_text(
+                Path::new("fragment.config"),
+                "\
+CONFIG_NET=y
+CONFIG_NET_VENDOR_FOO=y
+CONFIG_FOO_ETH=m
+# CONFIG_FOO_PTP is not set
+CONFIG_BAR_ETH=y
+CONFIG_SERIAL_BAZ=y
+CONFIG_UNUSED=y
+CONFIG_LOG_BUF_SHIFT=17
+",
+            )
+            .unwrap();
+
+        let minimized = minimize(&srctree, &kconfig, &fragment, &manifest).unwrap();
+
+        assert_eq!(
+            minimized.config.iter().collect::<Vec<_>>(),
+            [
+                ("NET", "y"),
+                ("NET_VENDOR_FOO", "y"),
+                ("FOO_ETH", "m"),
+                ("FOO_PTP", "n"),
+                ("SERIAL_BAZ", "y"),
+                ("LOG_BUF_SHIFT", "17"),
+                ("EXTRA", "y"),
+            ]
+        );
+        assert_eq!(minimized.removed, ["BAR_ETH", "UNUSED"]);
+        assert_eq!(minimized.added, ["EXTRA"]);
+        assert_eq!(
+            minimized.unresolved,
+            ["missing-drv", "acme,board", "acme,soc"]
+        );
+        // Only drivers/net/bar.o has been "built".
+        assert_eq!(minimized.object_bytes, Some(6));
+    }
+}
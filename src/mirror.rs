@@ -0,0 +1,168 @@
+use anyhow::{bail, Context};
+use reqwest::blocking::Client;
+use std::env;
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+
+/// The kernel.org CDN, used if no mirrors are configured.
+pub const DEFAULT_MIRROR: &str = "https://cdn.kernel.org/pub/linux/kernel";
+
+/// Environment variable holding a comma or whitespace separated mirror list.
+pub const MIRRORS_ENV: &str = "RUSTKRAZY_KERNEL_MIRRORS";
+
+/// An ordered list of kernel.org mirrors, each tried with retries
+/// and exponential backoff before moving on to the next one.
+///
+/// A mirror is the URL of the directory holding the `v*.x` release directories,
+/// e.g. `https://cdn.kernel.org/pub/linux/kernel` or `file:///srv/mirror/kernel`.
+#[derive(Debug)]
+pub struct Mirrors {
+    urls: Vec<String>,
+    retries: u32,
+}
+
+impl Mirrors {
+    /// Builds the mirror list from the first non-empty source out of
+    /// the command line, the environment and the mirror file.
+    /// The mirror file defaults to `$XDG_CONFIG_HOME/rustkrazy-kernel/mirrors`.
+    pub fn load(cli: &[String], file: Option<&Path>, retries: u32) -> anyhow::Result<Self> {
+        let mut urls = cli.to_vec();
+
+        if urls.is_empty() {
+            if let Some(list) = env::var_os(MIRRORS_ENV) {
+                urls = list
+                    .to_string_lossy()
+                    .split(|c: char| c == ',' || c.is_whitespace())
+                    .filter(|url| !url.is_empty())
+                    .map(String::from)
+                    .collect();
+            }
+        }
+
+        if urls.is_empty() {
+            let file = match file {
+                Some(file) => Some(file.to_path_buf()),
+                None => default_mirror_file().filter(|file| file.exists()),
+            };
+
+            if let Some(file) = file {
+                let list = fs::read_to_string(&file)
+                    .with_context(|| format!("failed to read mirror file {}", file.display()))?;
+
+                urls = list
+                    .lines()
+                    .map(|line| line.split('#').next().unwrap().trim())
+                    .filter(|url| !url.is_empty())
+                    .map(String::from)
+                    .collect();
+            }
+        }
+
+        if urls.is_empty() {
+            urls.push(DEFAULT_MIRROR.to_string());
+        }
+
+        for url in &urls {
+            if !["http://", "https://", "file://"]
+                .iter()
+                .any(|scheme| url.starts_with(scheme))
+            {
+                bail!(
+                    "unsupported mirror URL {} (expected http, https or file)",
+                    url
+                );
+            }
+        }
+
+        Ok(Self {
+            urls: urls
+                .into_iter()
+                .map(|url| url.trim_end_matches('/').to_string())
+                .collect(),
+            retries,
+        })
+    }
+
+    /// Calls `f` with the URL of `path` on each mirror in turn until it succeeds,
+    /// retrying each mirror with exponential backoff.
+    pub fn try_each<T>(
+        &self,
+        path: &str,
+        mut f: impl FnMut(&str) -> anyhow::Result<T>,
+    ) -> anyhow::Result<T> {
+        let mut errors = Vec::new();
+
+        for mirror in &self.urls {
+            let url = format!("{}/{}", mirror, path);
+
+            for attempt in 0..=self.retries {
+                if attempt > 0 {
+                    let backoff = Duration::from_secs(1 << (attempt - 1).min(6));
+                    println!("Retrying {} in {}s", url, backoff.as_secs());
+                    thread::sleep(backoff);
+                }
+
+                match f(&url) {
+                    Ok(v) => return Ok(v),
+                    Err(e) => {
+                        println!("Mirror {} failed: {}", mirror, e);
+
+                        if attempt == self.retries {
+                            errors.push(format!("{}: {:#}", mirror, e));
+                        }
+                    }
+                }
+            }
+        }
+
+        bail!("all mirrors failed for {}:\n{}", path, errors.join("\n"));
+    }
+
+    /// Downloads `path` from the first working mirror to `to`.
+    pub fn fetch(&self, path: &str, to: &Path) -> anyhow::Result<()> {
+        self.try_each(path, |url| download(url, to))
+    }
+}
+
+/// Reports whether `source` is fetched over HTTP(S) rather than read from disk.
+pub fn is_remote(source: &str) -> bool {
+    source.starts_with("http://") || source.starts_with("https://")
+}
+
+/// Returns the HTTP client used for all downloads.
+pub fn client() -> anyhow::Result<Client> {
+    // The default timeout covers the whole transfer which is too short for large tarballs.
+    Ok(Client::builder().timeout(None).build()?)
+}
+
+/// Opens an HTTP(S) or `file://` URL for reading. Anything else is treated as a local path.
+pub fn open(source: &str) -> anyhow::Result<Box<dyn Read>> {
+    if is_remote(source) {
+        Ok(Box::new(client()?.get(source).send()?.error_for_status()?))
+    } else {
+        let path = source.strip_prefix("file://").unwrap_or(source);
+        let file = File::open(path).with_context(|| format!("failed to open {}", path))?;
+
+        Ok(Box::new(file))
+    }
+}
+
+/// Downloads an HTTP(S) or `file://` URL to `to`. Anything else is treated as a local path.
+pub fn download(source: &str, to: &Path) -> anyhow::Result<()> {
+    let mut reader = open(source)?;
+    io::copy(&mut reader, &mut File::create(to)?)?;
+
+    Ok(())
+}
+
+fn default_mirror_file() -> Option<PathBuf> {
+    let config_home = match env::var_os("XDG_CONFIG_HOME").filter(|v| !v.is_empty()) {
+        Some(config_home) => PathBuf::from(config_home),
+        None => PathBuf::from(env::var_os("HOME")?).join(".config"),
+    };
+
+    Some(config_home.join("rustkrazy-kernel/mirrors"))
+}
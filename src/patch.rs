@@ -0,0 +1,129 @@
+use crate::no_stdin;
+
+use anyhow::{bail, Context};
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+
+/// A patch listed in a series file.
+#[derive(Debug)]
+pub struct Patch {
+    pub path: PathBuf,
+    /// Number of leading path components to strip (`-pN`).
+    pub strip: u32,
+}
+
+/// Reads the patches of a quilt-style patch directory in the order they apply.
+///
+/// The `series` file lists one patch per line relative to the directory,
+/// optionally followed by `-pN`. Blank lines and `#` comments are ignored.
+/// Without a `series` file all `*.patch` and `*.diff` files are used in name order.
+pub fn series(dir: &Path) -> anyhow::Result<Vec<Patch>> {
+    let series = dir.join("series");
+
+    if !series.exists() {
+        let mut patches = fs::read_dir(dir)
+            .with_context(|| format!("failed to read patch dir {}", dir.display()))?
+            .map(|entry| Ok(entry?.path()))
+            .collect::<io::Result<Vec<_>>>()?
+            .into_iter()
+            .filter(|path| {
+                path.extension()
+                    .is_some_and(|ext| ext == "patch" || ext == "diff")
+            })
+            .map(|path| Patch { path, strip: 1 })
+            .collect::<Vec<_>>();
+
+        patches.sort_by(|a, b| a.path.cmp(&b.path));
+        return Ok(patches);
+    }
+
+    let mut patches = Vec::new();
+    for (i, line) in fs::read_to_string(&series)?.lines().enumerate() {
+        let line = line.split('#').next().unwrap().trim();
+        if line.is_empty() {
+            continue;
+        }
+
+        let mut fields = line.split_whitespace();
+        let name = fields.next().unwrap();
+
+        let strip = match fields.next() {
+            None => 1,
+            Some(opt) => match opt.strip_prefix("-p").map(str::parse) {
+                Some(Ok(strip)) => strip,
+                _ => bail!(
+                    "{}:{}: invalid patch option {:?}",
+                    series.display(),
+                    i + 1,
+                    opt
+                ),
+            },
+        };
+
+        let path = dir.join(name);
+        if !path.is_file() {
+            bail!(
+                "{}:{}: patch {} not found",
+                series.display(),
+                i + 1,
+                path.display()
+            );
+        }
+
+        patches.push(Patch { path, strip });
+    }
+
+    Ok(patches)
+}
+
+/// Applies the patch series in `dir` to the tree at `src`, stopping at the first failure.
+/// Returns the number of patches applied.
+pub fn apply_series(src: &Path, dir: &Path) -> anyhow::Result<usize> {
+    let patches = series(dir)?;
+
+    for patch in &patches {
+        println!("Applying {}", patch.path.display());
+
+        apply(src, &mut File::open(&patch.path)?, patch.strip)
+            .with_context(|| format!("failed to apply {}", patch.path.display()))?;
+    }
+
+    Ok(patches.len())
+}
+
+/// Applies a unified diff read from `diff` to the tree at `dir` using `patch -pN`.
+/// The output of `patch` is included in the error if it fails.
+pub fn apply(dir: &Path, diff: &mut dyn Read, strip: u32) -> anyhow::Result<()> {
+    let mut patch = no_stdin("patch");
+    patch
+        .current_dir(dir)
+        .arg(format!("-p{}", strip))
+        .arg("--batch")
+        .arg("--forward")
+        .arg("--fuzz=0")
+        .arg("--silent")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+
+    let mut child = patch.spawn().context("failed to run patch")?;
+
+    // Keep going if patch exits early so its output can be reported.
+    let mut stdin = child.stdin.take().unwrap();
+    let copied = io::copy(diff, &mut stdin);
+    drop(stdin);
+
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        bail!(
+            "patch failed:\n{}{}",
+            String::from_utf8_lossy(&output.stdout),
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+
+    copied?;
+    Ok(())
+}
@@ -0,0 +1,159 @@
+use crate::mirror;
+use crate::version::Version;
+
+use anyhow::{bail, Context};
+use clap::ValueEnum;
+use serde::Deserialize;
+use std::fmt;
+use std::io::Read;
+
+/// Where kernel.org publishes the list of current releases.
+pub const RELEASES_URL: &str = "https://www.kernel.org/releases.json";
+
+/// A kernel.org release channel.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Channel {
+    /// The latest stable release.
+    Stable,
+    /// The newest longterm (LTS) release.
+    Longterm,
+    /// The latest release by Linus Torvalds (release candidates are not supported).
+    Mainline,
+}
+
+impl fmt::Display for Channel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Stable => write!(f, "stable"),
+            Self::Longterm => write!(f, "longterm"),
+            Self::Mainline => write!(f, "mainline"),
+        }
+    }
+}
+
+/// The parts of `releases.json` we use.
+#[derive(Debug, Deserialize)]
+pub struct Releases {
+    pub releases: Vec<Release>,
+}
+
+/// A single entry of `releases.json`.
+#[derive(Debug, Deserialize)]
+pub struct Release {
+    pub moniker: String,
+    pub version: String,
+    pub iseol: bool,
+}
+
+impl Releases {
+    /// Reads the release list from an HTTP(S) URL, a `file://` URL or a local path.
+    pub fn fetch(source: &str) -> anyhow::Result<Self> {
+        let mut json = String::new();
+        mirror::open(source)?
+            .read_to_string(&mut json)
+            .with_context(|| format!("failed to read {}", source))?;
+
+        serde_json::from_str(&json).with_context(|| format!("invalid release list {}", source))
+    }
+
+    /// Returns the current version of a release channel.
+    pub fn resolve(&self, channel: Channel) -> anyhow::Result<Version> {
+        let release = match self
+            .releases
+            .iter()
+            .find(|release| release.moniker == channel.to_string())
+        {
+            Some(release) => release,
+            None => bail!("no {} release listed", channel),
+        };
+
+        if release.version.contains("-rc") {
+            bail!(
+                "{} is at release candidate {} which is not published as a signed tarball",
+                channel,
+                release.version
+            );
+        }
+
+        release.version.parse()
+    }
+
+    /// Returns the state of the series `version` belongs to. Several entries
+    /// (e.g. mainline and stable) can belong to the same series.
+    pub fn series(&self, version: &Version) -> Option<Series<'_>> {
+        let releases = self
+            .releases
+            .iter()
+            .filter_map(|release| Some((release, release.version.parse::<Version>().ok()?)))
+            .filter(|(_, current)| current.base() == version.base())
+            .collect::<Vec<_>>();
+
+        let (release, current) = releases.iter().max_by_key(|(_, current)| *current)?;
+
+        Some(Series {
+            moniker: &release.moniker,
+            current: *current,
+            iseol: releases.iter().any(|(release, _)| release.iseol),
+        })
+    }
+}
+
+/// The state of a release series across all entries of `releases.json`.
+#[derive(Debug)]
+pub struct Series<'a> {
+    /// Channel of the newest release of the series.
+    pub moniker: &'a str,
+    /// The newest release of the series, e.g. `6.6.58` for `6.6.x`.
+    pub current: Version,
+    /// Whether any entry marks the series as end of life.
+    pub iseol: bool,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn releases(entries: &[(&str, &str, bool)]) -> Releases {
+        Releases {
+            releases: entries
+                .iter()
+                .map(|(moniker, version, iseol)| Release {
+                    moniker: moniker.to_string(),
+                    version: version.to_string(),
+                    iseol: *iseol,
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn series_takes_newest_release_across_entries() {
+        let releases = releases(&[
+            ("mainline", "6.18", false),
+            ("stable", "6.18.2", false),
+            ("longterm", "6.12.40", false),
+        ]);
+
+        let series = releases.series(&"6.18.1".parse().unwrap()).unwrap();
+        assert_eq!(series.moniker, "stable");
+        assert_eq!(series.current, "6.18.2".parse().unwrap());
+        assert!(!series.iseol);
+    }
+
+    #[test]
+    fn series_is_eol_if_any_entry_is() {
+        let releases = releases(&[("stable", "6.17.13", true), ("mainline", "6.17", false)]);
+
+        let series = releases.series(&"6.17.13".parse().unwrap()).unwrap();
+        assert_eq!(series.current, "6.17.13".parse().unwrap());
+        assert!(series.iseol);
+    }
+
+    #[test]
+    fn series_skips_unlisted_and_rc_entries() {
+        let releases = releases(&[("mainline", "6.19-rc3", false), ("stable", "6.18.2", false)]);
+
+        assert!(releases.series(&"6.19".parse().unwrap()).is_none());
+        assert!(releases.series(&"6.6.1".parse().unwrap()).is_none());
+    }
+}
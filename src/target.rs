@@ -0,0 +1,110 @@
+use anyhow::{bail, Context};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The targets built into the binary.
+const BUILTIN: &str = include_str!("../targets.json");
+
+/// A device or platform the kernel can be built for.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Target {
+    /// Name used with `--architecture` and in output file names.
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    /// Kernel architecture (`ARCH`).
+    pub arch: String,
+    /// `CROSS_COMPILE` prefix, if the target needs a cross compiler.
+    #[serde(default)]
+    pub cross_compile: Option<String>,
+    /// Make target building the kernel image in `arch/<arch>/boot`.
+    pub image: String,
+    /// Additional make targets, e.g. `dtbs` or `modules`.
+    #[serde(default)]
+    pub make_targets: Vec<String>,
+    /// Config fragments relative to the config directory,
+    /// merged after the architecture fragment.
+    #[serde(default)]
+    pub fragments: Vec<PathBuf>,
+    /// Device trees below `arch/<arch>/boot/dts` and the file names to copy them to.
+    #[serde(default)]
+    pub dtbs: BTreeMap<String, String>,
+    /// Other files of the built tree and the file names to copy them to.
+    #[serde(default)]
+    pub artifacts: BTreeMap<String, String>,
+    /// Files to write next to the kernel and their contents, e.g. a `config.txt`.
+    #[serde(default)]
+    pub files: BTreeMap<String, String>,
+    /// Profiles used if no `--profile` is given.
+    #[serde(default = "default_profiles")]
+    pub profiles: Vec<String>,
+}
+
+fn default_profiles() -> Vec<String> {
+    vec!["router".to_string(), "vpn".to_string()]
+}
+
+/// The known targets.
+#[derive(Debug)]
+pub struct Registry {
+    targets: Vec<Target>,
+}
+
+impl Registry {
+    /// Returns the built-in targets along with those from `files`.
+    /// Targets from files replace built-in ones of the same name.
+    pub fn load(files: &[PathBuf]) -> anyhow::Result<Self> {
+        let mut registry = Self {
+            targets: serde_json::from_str(BUILTIN).context("invalid built-in targets")?,
+        };
+
+        for file in files {
+            registry.merge_file(file)?;
+        }
+
+        Ok(registry)
+    }
+
+    fn merge_file(&mut self, path: &Path) -> anyhow::Result<()> {
+        let json = fs::read_to_string(path)
+            .with_context(|| format!("failed to read targets file {}", path.display()))?;
+        let targets: Vec<Target> = serde_json::from_str(&json)
+            .with_context(|| format!("invalid targets file {}", path.display()))?;
+
+        for target in targets {
+            match self
+                .targets
+                .iter_mut()
+                .find(|known| known.name == target.name)
+            {
+                Some(known) => *known = target,
+                None => self.targets.push(target),
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Looks up a target by name.
+    pub fn get(&self, name: &str) -> anyhow::Result<&Target> {
+        match self.targets.iter().find(|target| target.name == name) {
+            Some(target) => Ok(target),
+            None => bail!(
+                "invalid architecture {} (supported: {})",
+                name,
+                self.targets
+                    .iter()
+                    .map(|target| target.name.as_str())
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            ),
+        }
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Target> {
+        self.targets.iter()
+    }
+}
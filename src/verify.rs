@@ -0,0 +1,141 @@
+use crate::no_stdin;
+
+use anyhow::{bail, Context};
+use sha2::{Digest, Sha256};
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use xz2::read::XzDecoder;
+
+/// Primary key fingerprints of the kernel.org release signers.
+pub const KERNEL_ORG_KEYS: &[&str] = &[
+    // Linus Torvalds
+    "ABAF11C65A2970B130ABE3C479BE3E4300411886",
+    // Greg Kroah-Hartman
+    "647F28654894E3BD457199BE38DBBDC86092693E",
+    // Kernel.org checksum autosigner
+    "B8868C80BA62A1FFFAF5FDA9632D3A06589DA6B1",
+];
+
+/// Checks OpenPGP signatures against a keyring using `gpgv`,
+/// only accepting signatures made by a pinned set of keys.
+#[derive(Debug)]
+pub struct Verifier {
+    keyring: PathBuf,
+    trusted: Vec<String>,
+}
+
+impl Verifier {
+    /// Creates a verifier using the given binary keyring.
+    /// If `trusted` is empty the kernel.org release keys are trusted.
+    pub fn new(keyring: &Path, trusted: &[String]) -> anyhow::Result<Self> {
+        if !keyring.is_file() {
+            bail!(
+                "keyring {} not found (see README for how to create it)",
+                keyring.display()
+            );
+        }
+
+        let trusted = if trusted.is_empty() {
+            KERNEL_ORG_KEYS.iter().map(|key| key.to_string()).collect()
+        } else {
+            trusted
+                .iter()
+                .map(|key| key.replace(' ', "").to_uppercase())
+                .collect()
+        };
+
+        Ok(Self {
+            keyring: keyring.canonicalize()?,
+            trusted,
+        })
+    }
+
+    /// Verifies a clearsigned file and returns the signed text.
+    pub fn clearsigned(&self, path: &Path) -> anyhow::Result<String> {
+        let output = self.gpgv(&["--output".as_ref(), "-".as_ref(), path.as_os_str()], None)?;
+        Ok(String::from_utf8(output)?)
+    }
+
+    /// Verifies a detached signature over the data read from `data`.
+    pub fn detached(&self, signature: &Path, data: &mut dyn Read) -> anyhow::Result<()> {
+        self.gpgv(&[signature.as_os_str(), "-".as_ref()], Some(data))?;
+        Ok(())
+    }
+
+    fn gpgv(
+        &self,
+        args: &[&std::ffi::OsStr],
+        stdin: Option<&mut dyn Read>,
+    ) -> anyhow::Result<Vec<u8>> {
+        let mut gpgv = no_stdin("gpgv");
+        gpgv.arg("--status-fd")
+            .arg("2")
+            .arg("--keyring")
+            .arg(&self.keyring)
+            .args(args)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+
+        if stdin.is_some() {
+            gpgv.stdin(Stdio::piped());
+        }
+
+        let mut child = gpgv.spawn().context("failed to run gpgv")?;
+
+        if let Some(data) = stdin {
+            let mut pipe = child.stdin.take().unwrap();
+            io::copy(data, &mut pipe)?;
+        }
+
+        let output = child.wait_with_output()?;
+        let stderr = String::from_utf8_lossy(&output.stderr);
+
+        let signer = stderr
+            .lines()
+            .filter_map(|line| line.strip_prefix("[GNUPG:] VALIDSIG "))
+            .filter_map(|fields| fields.split_whitespace().last())
+            .find(|primary| self.trusted.iter().any(|key| key == primary));
+
+        if output.status.success() && signer.is_none() {
+            bail!("signature is valid but was not made by a trusted key");
+        } else if !output.status.success() {
+            let messages = stderr
+                .lines()
+                .filter(|line| !line.starts_with("[GNUPG:]"))
+                .collect::<Vec<_>>()
+                .join("\n");
+
+            bail!("gpgv rejected the signature:\n{}", messages);
+        }
+
+        Ok(output.stdout)
+    }
+}
+
+/// Returns the lowercase hex SHA-256 digest of a file.
+pub fn sha256(path: &Path) -> anyhow::Result<String> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut File::open(path)?, &mut hasher)?;
+
+    Ok(hex::encode(hasher.finalize()))
+}
+
+/// Looks up the expected digest of `file_name` in a `sha256sums` listing.
+pub fn expected_sha256<'a>(sums: &'a str, file_name: &str) -> Option<&'a str> {
+    sums.lines().find_map(|line| {
+        let (digest, name) = line.split_once(char::is_whitespace)?;
+        (name.trim_start().trim_start_matches('*') == file_name).then_some(digest)
+    })
+}
+
+/// Verifies the detached signature over the decompressed contents of an xz compressed file.
+pub fn signature(verifier: &Verifier, file: &Path, signature: &Path) -> anyhow::Result<()> {
+    let mut data = XzDecoder::new(File::open(file)?);
+    verifier
+        .detached(signature, &mut data)
+        .context("signature verification failed")?;
+
+    Ok(())
+}
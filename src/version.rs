@@ -0,0 +1,101 @@
+use anyhow::bail;
+use std::fmt;
+use std::str::FromStr;
+
+/// A kernel release number such as `6.6.58` or `6.12`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Version {
+    pub major: u32,
+    pub minor: u32,
+    pub patch: Option<u32>,
+}
+
+impl Version {
+    /// Directory on kernel.org holding all releases of this major version, e.g. `v6.x`.
+    pub fn series_dir(&self) -> String {
+        format!("v{}.x", self.major)
+    }
+
+    /// The first release of the series, e.g. `6.15` for `6.15.4`.
+    pub fn base(&self) -> Self {
+        Self {
+            patch: None,
+            ..*self
+        }
+    }
+
+    /// Name of the unpacked source tree, e.g. `linux-6.15.4`.
+    pub fn name(&self) -> String {
+        format!("linux-{}", self)
+    }
+
+    /// File name of the release tarball, e.g. `linux-6.15.4.tar.xz`.
+    pub fn tarball(&self) -> String {
+        format!("{}.tar.xz", self.name())
+    }
+
+    /// Path of the release tarball relative to a kernel.org mirror.
+    pub fn tarball_path(&self) -> String {
+        format!("{}/{}", self.series_dir(), self.tarball())
+    }
+
+    /// Path of the stable patch from the base release to this one
+    /// relative to a kernel.org mirror, if this is a stable release.
+    pub fn patch_path(&self) -> Option<String> {
+        self.patch?;
+        Some(format!("{}/patch-{}.xz", self.series_dir(), self))
+    }
+
+    /// Path of the signed checksum list covering this release relative to a kernel.org mirror.
+    pub fn checksums_path(&self) -> String {
+        format!("{}/sha256sums.asc", self.series_dir())
+    }
+}
+
+impl fmt::Display for Version {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}", self.major, self.minor)?;
+
+        if let Some(patch) = self.patch {
+            write!(f, ".{}", patch)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl FromStr for Version {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> anyhow::Result<Self> {
+        let s = s.trim_start_matches("linux-").trim_start_matches('v');
+
+        let parts = s
+            .split('.')
+            .map(|part| part.parse::<u32>())
+            .collect::<Result<Vec<_>, _>>();
+
+        let version = match parts.as_deref() {
+            Ok([major, minor]) => Self {
+                major: *major,
+                minor: *minor,
+                patch: None,
+            },
+            Ok([major, minor, patch]) => Self {
+                major: *major,
+                minor: *minor,
+                patch: Some(*patch),
+            },
+            _ => bail!(
+                "invalid kernel version {:?} (expected e.g. 6.6.58 or 6.12)",
+                s
+            ),
+        };
+
+        if version.major < 3 {
+            bail!("kernel versions before 3.0 are not supported");
+        }
+
+        Ok(version)
+    }
+}
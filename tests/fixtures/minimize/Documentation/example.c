@@ -0,0 +1,2 @@
+/* Never scanned, Documentation/ doesn't hold drivers. */
+static const char *compatible = "acme,baz-uart";
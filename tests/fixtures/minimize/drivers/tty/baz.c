@@ -0,0 +1,4 @@
+static const struct of_device_id baz_of_match[] = {
+	{ .compatible = "acme,baz-uart" },
+	{}
+};